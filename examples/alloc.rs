@@ -0,0 +1,47 @@
+//! Count the heap allocations made per `tlog!` line:
+//!
+//! ```text
+//! $ cargo run --release --example alloc
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tlog::tlog;
+
+struct Counting;
+
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn main() {
+    let n = 10_000;
+    // the first line opens the file and sets up the caches
+    tlog!("warm up");
+    let before = ALLOCS.load(Ordering::Relaxed);
+    for i in 0..n {
+        tlog!("alloc line {}", i);
+    }
+    for _ in 0..n {
+        tlog!("alloc line");
+    }
+    let per_line = (ALLOCS.load(Ordering::Relaxed) - before) as f64 / (2 * n) as f64;
+    println!("{:.1} allocations per line", per_line);
+}
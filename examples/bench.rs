@@ -0,0 +1,12 @@
+use std::time::Instant;
+use tlog::tlog;
+
+fn main() {
+    let n = 100_000;
+    let start = Instant::now();
+    for i in 0..n {
+        tlog!("bench line {}", i);
+    }
+    tlog::flush().unwrap();
+    println!("{} lines in {:?}", n, start.elapsed());
+}
@@ -1,8 +1,9 @@
-use tlog::{tlog, type_name};
+use tlog::{tlog, tlog_warn, type_name};
 
 fn main() {
     tlog!("hello");
 
     let n = 3;
     tlog!("5 x 7 = {}, {}", 5 * 7, type_name(&n));
+    tlog_warn!("{} is a small number", n);
 }
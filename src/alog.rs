@@ -0,0 +1,72 @@
+//! Logging from async code without blocking the runtime, with the
+//! `tokio` feature.
+
+use std::future::Future;
+use std::io;
+use std::sync::mpsc::{self, Sender};
+use std::sync::OnceLock;
+use tokio::sync::oneshot;
+
+type Job = (String, oneshot::Sender<io::Result<()>>);
+
+/// The queue of the thread writing the lines of `alog`, one after
+/// another in the order they were queued. `None` if it couldn't start.
+fn queue() -> Option<&'static Sender<Job>> {
+    static QUEUE: OnceLock<Option<Sender<Job>>> = OnceLock::new();
+    QUEUE
+        .get_or_init(|| {
+            let (tx, rx) = mpsc::channel::<Job>();
+            let spawned = std::thread::Builder::new().name("tlog-alog".to_string()).spawn(move || {
+                for (msg, done) in rx {
+                    let _ = done.send(crate::log_line(&msg));
+                }
+            });
+            spawned.ok().map(|_| tx)
+        })
+        .as_ref()
+}
+
+/// Log `msg` like `log_line`, with the file write done by a tlog thread
+/// instead of the calling task's worker thread.
+///
+/// The line is queued when `alog` is called, not when the future is
+/// first polled, so lines land in the file in the order of the calls,
+/// however the futures are awaited: one after another, with `join!`, or
+/// spawned. The future resolves once the line is written.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-alog-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+/// rt.block_on(async {
+///     for i in 0..3 {
+///         tlog::alog(format!("step {}", i)).await.unwrap();
+///     }
+///     let (a, b) = tokio::join!(tlog::alog("step 3"), tlog::alog("step 4"));
+///     a.and(b).unwrap();
+/// });
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # let steps: Vec<_> = content.lines().map(|l| l.rsplit("] ").next().unwrap()).collect();
+/// # assert_eq!(steps, ["step 0", "step 1", "step 2", "step 3", "step 4"]);
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+pub fn alog(msg: impl Into<String>) -> impl Future<Output = io::Result<()>> {
+    let (done, written) = oneshot::channel();
+    let job = (msg.into(), done);
+    let job = match queue() {
+        Some(queue) => queue.send(job).err().map(|e| e.0),
+        None => Some(job),
+    };
+    if let Some((msg, done)) = job {
+        // no writer thread: write right away rather than lose the line
+        let _ = done.send(crate::log_line(&msg));
+    }
+    async move {
+        match written.await {
+            Ok(result) => result,
+            Err(_) => Err(io::Error::other("tlog: the alog writer thread is gone")),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! `tlog_batch!`: the lines of a block collected per thread and written
+//! in one go when it ends.
+
+use std::cell::RefCell;
+
+/// Lines waiting for the end of the batch, by target.
+struct Pending {
+    target: Option<String>,
+    data: Vec<u8>,
+}
+
+#[derive(Default)]
+struct Batch {
+    /// How many `tlog_batch!` blocks the thread is in.
+    depth: usize,
+    pending: Vec<Pending>,
+}
+
+thread_local! {
+    static BATCH: RefCell<Batch> = RefCell::new(Batch::default());
+}
+
+/// Held for the duration of a `tlog_batch!` block; writes the lines out
+/// when the outermost one is dropped, even when unwinding.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct BatchGuard {
+    _private: (),
+}
+
+#[doc(hidden)]
+pub fn __batch() -> BatchGuard {
+    let _ = BATCH.try_with(|b| b.borrow_mut().depth += 1);
+    BatchGuard { _private: () }
+}
+
+impl Drop for BatchGuard {
+    fn drop(&mut self) {
+        let pending = BATCH.try_with(|b| {
+            let mut b = b.borrow_mut();
+            b.depth -= 1;
+            if b.depth == 0 { std::mem::take(&mut b.pending) } else { Vec::new() }
+        });
+        for p in pending.unwrap_or_default() {
+            // a debug logger must never take the program down with it
+            let _ = crate::send(p.target.as_deref(), &p.data);
+        }
+    }
+}
+
+/// Keep `data` for the end of the batch if the thread is in one, and
+/// say whether it did.
+pub(crate) fn push(target: Option<&str>, data: &[u8]) -> bool {
+    BATCH
+        .try_with(|b| {
+            let mut b = b.borrow_mut();
+            if b.depth == 0 {
+                return false;
+            }
+            match b.pending.last_mut() {
+                Some(last) if last.target.as_deref() == target => last.data.extend_from_slice(data),
+                _ => b.pending.push(Pending { target: target.map(String::from), data: data.to_vec() }),
+            }
+            true
+        })
+        .unwrap_or(false)
+}
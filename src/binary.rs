@@ -0,0 +1,108 @@
+//! `tlog_bin!`: compact binary records for machine consumption, next to
+//! the text log.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{getpid, DateTime};
+
+/// One record of the binary log, see `tlog_bin!`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogRecord {
+    /// Milliseconds since the Unix epoch.
+    pub ts_unix_ms: i64,
+    pub pid: i32,
+    pub msg: String,
+}
+
+/// The binary log: the log file with a `.bin` extension, so `t.bin` in
+/// the temp directory by default.
+pub fn bin_file_path() -> PathBuf {
+    crate::log_file_path().with_extension("bin")
+}
+
+/// The binary log kept open between calls, with its path.
+static BIN_FILE: Mutex<Option<(PathBuf, File)>> = Mutex::new(None);
+
+/// Append a record for `msg` to the binary log: its length as a
+/// little-endian `u32`, then the record in postcard.
+pub(crate) fn write(msg: &str) -> io::Result<()> {
+    let record = LogRecord {
+        ts_unix_ms: DateTime::now().unix_timestamp_millis(),
+        pid: getpid(),
+        msg: msg.to_string(),
+    };
+    let body = postcard::to_allocvec(&record).map_err(io::Error::other)?;
+    let mut frame = Vec::with_capacity(4 + body.len());
+    frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&body);
+
+    let path = bin_file_path();
+    let mut cached = BIN_FILE.lock().unwrap_or_else(|e| e.into_inner());
+    let reopen = match *cached {
+        Some((ref p, _)) => *p != path || !path.exists(),
+        None => true,
+    };
+    if reopen {
+        *cached = None;
+        let file = crate::open_append(&path)?;
+        *cached = Some((path, file));
+    }
+    match *cached {
+        Some((_, ref mut file)) => file.write_all(&frame),
+        None => Ok(()),
+    }
+}
+
+/// The records of a binary log, see `read_bin`.
+#[derive(Debug)]
+pub struct BinRecords {
+    reader: BufReader<File>,
+}
+
+/// Read back the records written by `tlog_bin!` to `path`, oldest
+/// first. Reading stops at the end of the file, or at a record that is
+/// cut short or doesn't decode, as the last one may be while it is being
+/// written.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-bin-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use tlog::tlog_bin;
+///
+/// for i in 0..3 {
+///     tlog_bin!("record {}", i);
+/// }
+/// let records: Vec<_> = tlog::read_bin(tlog::bin_file_path()).unwrap().collect();
+/// assert_eq!(records.len(), 3);
+/// assert_eq!(records[2].msg, "record 2");
+/// assert_eq!(records[0].pid, std::process::id() as i32);
+/// assert!(records[0].ts_unix_ms <= records[2].ts_unix_ms);
+/// # std::fs::remove_file(tlog::bin_file_path()).unwrap();
+/// # }
+/// ```
+pub fn read_bin(path: impl AsRef<Path>) -> io::Result<BinRecords> {
+    Ok(BinRecords { reader: BufReader::new(File::open(path)?) })
+}
+
+impl Iterator for BinRecords {
+    type Item = LogRecord;
+
+    fn next(&mut self) -> Option<LogRecord> {
+        let mut len = [0; 4];
+        self.reader.read_exact(&mut len).ok()?;
+        let len = u32::from_le_bytes(len) as usize;
+        // not `vec![0; len]`: a garbled length could ask for gigabytes
+        let mut body = Vec::new();
+        (&mut self.reader).take(len as u64).read_to_end(&mut body).ok()?;
+        if body.len() != len {
+            return None;
+        }
+        postcard::from_bytes(&body).ok()
+    }
+}
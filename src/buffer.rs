@@ -0,0 +1,108 @@
+//! `TMP_LOG_BUFFERED=1`: lines are collected in memory and written by a
+//! background thread every `FLUSH_INTERVAL`, or sooner by `tlog::flush()`.
+
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, Once};
+use std::thread;
+use std::time::Duration;
+
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Past this many buffered bytes (or env `TMP_LOG_BUFFER_BYTES`) the
+/// caller writes them out itself, or drops its line with `TMP_LOG_DROP=1`.
+const CAPACITY: usize = 1 << 20;
+
+/// Lines dropped with `TMP_LOG_DROP=1` since the last note about them.
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Lines waiting to be written to `path`.
+struct Pending {
+    path: String,
+    data: Vec<u8>,
+}
+
+static BUFFER: Mutex<Vec<Pending>> = Mutex::new(Vec::new());
+
+/// Held while writing pending lines out, so that two flushes can't
+/// reorder them.
+static FLUSHING: Mutex<()> = Mutex::new(());
+
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub(crate) fn enabled() -> bool {
+    crate::env_flag("TMP_LOG_BUFFERED")
+}
+
+fn capacity() -> usize {
+    match std::env::var("TMP_LOG_BUFFER_BYTES").ok().and_then(|x| x.trim().parse().ok()) {
+        Some(n) if n > 0 => n,
+        _ => CAPACITY,
+    }
+}
+
+/// Queue `data` for `path`.
+///
+/// When the buffer is full the caller flushes it, waiting for the disk,
+/// unless env `TMP_LOG_DROP=1` is set: then `data` is dropped instead,
+/// and counted for a `dropped N messages` note on the next flush.
+pub(crate) fn push(path: String, data: &[u8]) -> io::Result<()> {
+    start_flusher();
+    let capacity = capacity();
+    let drop = crate::env_flag("TMP_LOG_DROP");
+    let full = {
+        let mut buf = lock(&BUFFER);
+        let len = buf.iter().map(|p| p.data.len()).sum::<usize>();
+        if drop && len + data.len() > capacity {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        match buf.last_mut() {
+            Some(last) if last.path == path => last.data.extend_from_slice(data),
+            _ => buf.push(Pending { path, data: data.to_vec() }),
+        }
+        len + data.len() > capacity
+    };
+    if full {
+        flush()?;
+    }
+    Ok(())
+}
+
+/// Write out everything buffered so far, followed by a note on the
+/// lines dropped since the last flush, if any.
+pub(crate) fn flush() -> io::Result<()> {
+    let _flushing = lock(&FLUSHING);
+    let pending = std::mem::take(&mut *lock(&BUFFER));
+    let mut result = Ok(());
+    for p in pending {
+        if let Err(e) = crate::write_to_file(&p.path, &p.data) {
+            result = Err(e);
+        }
+    }
+    let dropped = DROPPED.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        let msg = format!("dropped {} messages", dropped);
+        let note = crate::Config::from_env().render(None, None, &msg, &crate::SEQ);
+        if let Err(e) = crate::write_to_file(&crate::log_file(), note.as_bytes()) {
+            result = Err(e);
+        }
+    }
+    result
+}
+
+fn start_flusher() {
+    static START: Once = Once::new();
+    START.call_once(|| {
+        let spawned = thread::Builder::new()
+            .name("tlog-flush".to_string())
+            .spawn(|| loop {
+                thread::sleep(FLUSH_INTERVAL);
+                let _ = flush();
+            });
+        // without the thread lines still go out on flush() or when full
+        drop(spawned);
+    });
+}
@@ -0,0 +1,427 @@
+use std::fmt;
+use time::error::{ComponentRange, IndeterminateOffset};
+use time::{OffsetDateTime, UtcOffset};
+
+/// How many fractional second digits a `DateTime` renders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Precision {
+    /// `.123`
+    #[default]
+    Millis,
+    /// `.123456`
+    Micros,
+    /// `.123456789`
+    Nanos,
+}
+
+impl Precision {
+    /// The precision from env `TMP_LOG_PRECISION` (`ms`, `us` or `ns`),
+    /// milliseconds when unset or unknown.
+    pub fn from_env() -> Self {
+        match std::env::var("TMP_LOG_PRECISION") {
+            Ok(x) => match x.trim() {
+                "us" => Precision::Micros,
+                "ns" => Precision::Nanos,
+                _ => Precision::Millis,
+            },
+            Err(_) => Precision::Millis,
+        }
+    }
+}
+
+/// A timestamp as written in the log lines.
+///
+/// `DateTime`s compare by the instant they represent, whatever their UTC
+/// offsets:
+///
+/// ```
+/// use time::{OffsetDateTime, UtcOffset};
+/// use tlog::DateTime;
+///
+/// let at = |secs| OffsetDateTime::from_unix_timestamp(secs).unwrap();
+/// let east = UtcOffset::from_hms(8, 0, 0).unwrap();
+/// let west = UtcOffset::from_hms(-5, 0, 0).unwrap();
+///
+/// let mut v = vec![
+///     DateTime::from(at(300).to_offset(west)),
+///     DateTime::from(at(100).to_offset(east)),
+///     DateTime::from(at(200)),
+/// ];
+/// v.sort();
+/// assert_eq!(v, [
+///     DateTime::from(at(100)),
+///     DateTime::from(at(200)),
+///     DateTime::from(at(300)),
+/// ]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    odt: OffsetDateTime,
+}
+
+impl DateTime {
+    /// The current time in the local offset, falling back to UTC when the
+    /// local offset can't be determined.
+    pub fn now() -> Self {
+        #[cfg(feature = "test-util")]
+        if let Some(ref clock) = *CLOCK.read().unwrap_or_else(|e| e.into_inner()) {
+            return DateTime { odt: clock() };
+        }
+        match DateTime::now_local() {
+            Ok(dt) => dt,
+            Err(_) => DateTime::now_utc(),
+        }
+    }
+
+    /// The current time in UTC.
+    ///
+    /// ```
+    /// use tlog::DateTime;
+    ///
+    /// assert!(DateTime::now_utc().offset().is_utc());
+    /// // may fail, e.g. in a multithreaded process on some platforms
+    /// if let Ok(dt) = DateTime::now_local() {
+    ///     assert!(dt.unix_timestamp() > 0);
+    /// }
+    /// ```
+    pub fn now_utc() -> Self {
+        DateTime { odt: OffsetDateTime::now_utc() }
+    }
+
+    /// The current time in the local offset. `time` refuses to look the
+    /// offset up when it can't do so soundly, notably once other threads
+    /// are running on some Unix platforms.
+    pub fn now_local() -> Result<Self, IndeterminateOffset> {
+        Ok(DateTime { odt: OffsetDateTime::now_local()? })
+    }
+
+    /// The UTC offset as written with `TMP_LOG_TZ=1`: `Z` for UTC, else
+    /// like `+08:00`.
+    ///
+    /// ```
+    /// use time::{OffsetDateTime, UtcOffset};
+    /// use tlog::DateTime;
+    ///
+    /// let odt = OffsetDateTime::from_unix_timestamp(1_662_376_231).unwrap();
+    /// let east = UtcOffset::from_hms(8, 0, 0).unwrap();
+    /// let west = UtcOffset::from_hms(-5, -30, 0).unwrap();
+    /// assert_eq!(DateTime::from(odt).offset_suffix(), "Z");
+    /// assert_eq!(DateTime::from(odt.to_offset(east)).offset_suffix(), "+08:00");
+    /// assert_eq!(DateTime::from(odt.to_offset(west)).offset_suffix(), "-05:30");
+    ///
+    /// std::env::set_var("TMP_LOG_TZ", "1");
+    /// assert_eq!(DateTime::from(odt).to_string(), "2022-09-05 11:10:31.000Z");
+    /// assert_eq!(DateTime::from(odt.to_offset(east)).to_string(), "2022-09-05 19:10:31.000+08:00");
+    /// ```
+    pub fn offset_suffix(&self) -> String {
+        let offset = self.odt.offset();
+        if offset.is_utc() {
+            return "Z".to_string();
+        }
+        let sign = if offset.is_negative() { '-' } else { '+' };
+        format!("{}{:02}:{:02}", sign, offset.whole_hours().abs(), offset.minutes_past_hour().abs())
+    }
+
+    /// The calendar date, in this timestamp's offset.
+    pub fn date(&self) -> time::Date {
+        self.odt.date()
+    }
+
+    /// The UTC offset of this timestamp.
+    pub fn offset(&self) -> UtcOffset {
+        self.odt.offset()
+    }
+
+    /// The `DateTime` `secs` seconds after the Unix epoch, in the local
+    /// offset when it can be determined and UTC otherwise.
+    ///
+    /// ```
+    /// use tlog::DateTime;
+    ///
+    /// let dt = DateTime::from_unix_timestamp(1_662_376_231).unwrap();
+    /// assert_eq!(dt.unix_timestamp(), 1_662_376_231);
+    /// let dt = DateTime::from_unix_timestamp_millis(1_662_376_231_763).unwrap();
+    /// assert!(dt.to_string().ends_with(".763"));
+    /// assert_eq!(dt.unix_timestamp(), 1_662_376_231);
+    /// assert_eq!(dt.unix_timestamp_millis(), 1_662_376_231_763);
+    /// ```
+    pub fn from_unix_timestamp(secs: i64) -> Result<Self, ComponentRange> {
+        let odt = OffsetDateTime::from_unix_timestamp(secs)?;
+        Ok(DateTime { odt: odt.to_offset(local_offset()) })
+    }
+
+    /// Like `from_unix_timestamp`, from milliseconds since the epoch.
+    pub fn from_unix_timestamp_millis(millis: i64) -> Result<Self, ComponentRange> {
+        let odt = OffsetDateTime::from_unix_timestamp_nanos(millis as i128 * 1_000_000)?;
+        Ok(DateTime { odt: odt.to_offset(local_offset()) })
+    }
+
+    /// Seconds since the Unix epoch.
+    pub fn unix_timestamp(&self) -> i64 {
+        self.odt.unix_timestamp()
+    }
+
+    /// Milliseconds since the Unix epoch.
+    pub fn unix_timestamp_millis(&self) -> i64 {
+        (self.odt.unix_timestamp_nanos() / 1_000_000) as i64
+    }
+
+    /// Parse a timestamp the way `Display` writes it, `2022-09-05
+    /// 11:10:31.763` or with the `T` of `{:#}`, with 1 to 9 fractional
+    /// digits and an optional `Z` or `+08:00` offset. Without one the
+    /// local offset is assumed.
+    ///
+    /// ```
+    /// use tlog::DateTime;
+    ///
+    /// let dt = DateTime::from_unix_timestamp_millis(1_662_376_231_763).unwrap();
+    /// assert_eq!(DateTime::parse(&dt.to_string()), Ok(dt));
+    /// assert_eq!(DateTime::parse(&format!("{:#}", dt)), Ok(dt));
+    /// assert_eq!("2022-09-05T11:10:31.763Z".parse::<DateTime>().unwrap().unix_timestamp(), 1_662_376_231);
+    /// assert!(DateTime::parse("2022-09-05").is_err());
+    /// ```
+    pub fn parse(s: &str) -> Result<Self, ParseError> {
+        parse(s).ok_or_else(|| ParseError { input: s.to_string() })
+    }
+
+    /// How long after `other` this timestamp is, negative if it is
+    /// before it.
+    ///
+    /// ```
+    /// use tlog::DateTime;
+    ///
+    /// let start = DateTime::from_unix_timestamp_millis(1_662_376_231_763).unwrap();
+    /// let end = DateTime::from_unix_timestamp_millis(1_662_376_233_013).unwrap();
+    /// assert_eq!(end.elapsed_since(&start), time::Duration::milliseconds(1250));
+    /// assert_eq!(start.elapsed_since(&end), time::Duration::milliseconds(-1250));
+    /// ```
+    pub fn elapsed_since(&self, other: &DateTime) -> time::Duration {
+        self.odt - other.odt
+    }
+
+    /// Format like `Display` does, but with an explicit precision instead
+    /// of the one from `TMP_LOG_PRECISION`.
+    ///
+    /// ```
+    /// use tlog::{DateTime, Precision};
+    ///
+    /// let odt = time::OffsetDateTime::from_unix_timestamp_nanos(1_662_376_231_763_123_456).unwrap();
+    /// let dt = DateTime::from(odt);
+    /// assert_eq!(dt.format_with_precision(Precision::Millis), "2022-09-05 11:10:31.763");
+    /// assert_eq!(dt.format_with_precision(Precision::Micros), "2022-09-05 11:10:31.763123");
+    /// assert_eq!(dt.format_with_precision(Precision::Nanos), "2022-09-05 11:10:31.763123456");
+    /// ```
+    pub fn format_with_precision(&self, p: Precision) -> String {
+        let mut s = String::with_capacity(29);
+        self.write_with_precision(&mut s, p);
+        s
+    }
+
+    /// Append what `format_with_precision` returns to `out`.
+    pub(crate) fn write_with_precision(&self, out: &mut String, p: Precision) {
+        use std::fmt::Write as _;
+        let _ = write!(out, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.odt.year(),
+            self.odt.month() as u8,
+            self.odt.day(),
+            self.odt.hour(),
+            self.odt.minute(),
+            self.odt.second(),
+        );
+        let _ = match p {
+            Precision::Millis => write!(out, ".{:03}", self.odt.millisecond()),
+            Precision::Micros => write!(out, ".{:06}", self.odt.microsecond()),
+            Precision::Nanos => write!(out, ".{:09}", self.odt.nanosecond()),
+        };
+    }
+}
+
+#[cfg(feature = "test-util")]
+type Clock = Box<dyn Fn() -> OffsetDateTime + Send + Sync>;
+
+#[cfg(feature = "test-util")]
+static CLOCK: std::sync::RwLock<Option<Clock>> = std::sync::RwLock::new(None);
+
+/// Make `DateTime::now()`, and so every log line, use `clock` instead of
+/// the system time, e.g. to freeze it in tests.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let dir = std::env::temp_dir().join(format!("tlog-clock-{}", std::process::id()));
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// # let path = dir.join("t.log");
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use time::OffsetDateTime;
+///
+/// let at = |secs| move || OffsetDateTime::from_unix_timestamp(secs).unwrap();
+/// tlog::set_clock_for_test(at(1_662_376_231));
+/// std::env::set_var("TMP_LOG_DAILY", "1");
+/// tlog::tlog!("frozen");
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # assert_eq!(content, format!("[2022-09-05 11:10:31.000][{}] frozen\n", std::process::id()));
+///
+/// // a day later the file of the 5th is moved aside
+/// tlog::set_clock_for_test(at(1_662_376_231 + 86400));
+/// tlog::tlog!("the 6th");
+/// tlog::reset_clock_for_test();
+/// # let old = std::fs::read_to_string(dir.join("t.log.2022-09-05")).unwrap();
+/// # assert_eq!(old, content);
+/// # let new = std::fs::read_to_string(&path).unwrap();
+/// # assert!(new.starts_with("[2022-09-06 11:10:31.000]") && new.ends_with("] the 6th\n"));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// # }
+/// ```
+#[cfg(feature = "test-util")]
+pub fn set_clock_for_test(clock: impl Fn() -> OffsetDateTime + Send + Sync + 'static) {
+    *CLOCK.write().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(clock));
+}
+
+/// Go back to the system time after `set_clock_for_test`.
+#[cfg(feature = "test-util")]
+pub fn reset_clock_for_test() {
+    *CLOCK.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// The local UTC offset, or UTC when it can't be determined.
+fn local_offset() -> UtcOffset {
+    UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC)
+}
+
+/// Parse the `YYYY-MM-DD HH:MM:SS.fff` layout written by `Display`, with
+/// any number of fractional digits from 1 to 9 and an optional `Z` or
+/// `+08:00` suffix. Without one the local offset is assumed.
+pub(crate) fn parse(s: &str) -> Option<DateTime> {
+    let b = s.as_bytes();
+    if b.len() < 21 || b[4] != b'-' || b[7] != b'-' || b[13] != b':' || b[16] != b':' || b[19] != b'.' {
+        return None;
+    }
+    if b[10] != b' ' && b[10] != b'T' {
+        return None;
+    }
+    let num = |r: std::ops::Range<usize>| -> Option<u32> {
+        let part = s.get(r)?;
+        if part.bytes().all(|c| c.is_ascii_digit()) { part.parse().ok() } else { None }
+    };
+    let rest = s.get(20..)?;
+    let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+    if digits == 0 || digits > 9 {
+        return None;
+    }
+    let nanos = num(20..20 + digits)? * 10u32.pow(9 - digits as u32);
+    let offset = match &rest[digits..] {
+        "" => local_offset(),
+        suffix => parse_offset(suffix)?,
+    };
+
+    let month = time::Month::try_from(num(5..7)? as u8).ok()?;
+    let date = time::Date::from_calendar_date(num(0..4)? as i32, month, num(8..10)? as u8).ok()?;
+    let time = time::Time::from_hms_nano(num(11..13)? as u8, num(14..16)? as u8, num(17..19)? as u8, nanos).ok()?;
+    let odt = time::PrimitiveDateTime::new(date, time).assume_offset(offset);
+    Some(DateTime { odt })
+}
+
+/// `Z`, `+08:00` or `-05:30`.
+fn parse_offset(s: &str) -> Option<UtcOffset> {
+    if s == "Z" {
+        return Some(UtcOffset::UTC);
+    }
+    let b = s.as_bytes();
+    if b.len() != 6 || b[3] != b':' || !(b[0] == b'+' || b[0] == b'-') {
+        return None;
+    }
+    let hours: i8 = s.get(1..3)?.parse().ok()?;
+    let minutes: i8 = s.get(4..6)?.parse().ok()?;
+    let sign = if b[0] == b'-' { -1 } else { 1 };
+    UtcOffset::from_hms(sign * hours, sign * minutes, 0).ok()
+}
+
+/// The error of `DateTime::parse`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    input: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid tlog timestamp: {}", self.input)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl std::str::FromStr for DateTime {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        DateTime::parse(s)
+    }
+}
+
+impl From<OffsetDateTime> for DateTime {
+    fn from(odt: OffsetDateTime) -> Self {
+        DateTime { odt }
+    }
+}
+
+/// The timestamp as written in the log, `2022-09-05 11:10:31.763`, with
+/// the precision of `TMP_LOG_PRECISION` and the offset when `TMP_LOG_TZ`
+/// is on. The alternate form `{:#}` has the ISO 8601 `T` between the
+/// date and the time instead of a space:
+///
+/// ```
+/// let odt = time::OffsetDateTime::from_unix_timestamp_nanos(1_662_376_231_763_000_000).unwrap();
+/// let dt = tlog::DateTime::from(odt);
+/// assert_eq!(format!("{}", dt), "2022-09-05 11:10:31.763");
+/// assert_eq!(format!("{:#}", dt), "2022-09-05T11:10:31.763");
+/// ```
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = self.format_with_precision(Precision::from_env());
+        if f.alternate() {
+            s.replace_range(10..11, "T");
+        }
+        f.write_str(&s)?;
+        if crate::env_flag("TMP_LOG_TZ") {
+            f.write_str(&self.offset_suffix())?;
+        }
+        Ok(())
+    }
+}
+
+/// Serialized as the string `Display` writes by default,
+/// `2022-09-05 11:10:31.763`, whatever `TMP_LOG_PRECISION` and
+/// `TMP_LOG_TZ` say. That string has no sub-millisecond digits and no
+/// offset: those are lost, and it reads back in the local offset of the
+/// reading process. Only a whole-millisecond value serialized and read
+/// back in the same offset comes back equal. Deserializing also accepts
+/// the other forms of `DateTime::parse`, like `2022-09-05T11:10:31.763Z`.
+///
+/// ```
+/// use tlog::DateTime;
+///
+/// std::env::set_var("TMP_LOG_PRECISION", "ns");
+/// std::env::set_var("TMP_LOG_TZ", "1");
+/// let dt = DateTime::from_unix_timestamp_millis(1_662_376_231_763).unwrap();
+/// let json = serde_json::to_string(&dt).unwrap();
+/// assert_eq!(json.len(), "\"2022-09-05 11:10:31.763\"".len());
+/// assert!(json.ends_with(".763\""));
+/// let back: DateTime = serde_json::from_str(&json).unwrap();
+/// assert_eq!(back, dt);
+///
+/// let utc: DateTime = serde_json::from_str("\"2022-09-05T11:10:31.763Z\"").unwrap();
+/// assert_eq!(utc.unix_timestamp_millis(), 1_662_376_231_763);
+/// ```
+#[cfg(feature = "serde")]
+impl serde::Serialize for DateTime {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.format_with_precision(Precision::Millis))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for DateTime {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        DateTime::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,41 @@
+//! Dropping noisy messages with `TMP_LOG_EXCLUDE`.
+
+/// Whether `msg` should not be written: it contains one of the comma
+/// separated terms of env `TMP_LOG_EXCLUDE`, or, with the `regex`
+/// feature, matches env `TMP_LOG_EXCLUDE_RE`.
+pub(crate) fn excluded(msg: &str) -> bool {
+    if let Ok(terms) = std::env::var("TMP_LOG_EXCLUDE") {
+        if terms.split(',').any(|term| !term.is_empty() && msg.contains(term)) {
+            return true;
+        }
+    }
+    excluded_re(msg)
+}
+
+#[cfg(feature = "regex")]
+fn excluded_re(msg: &str) -> bool {
+    use regex::Regex;
+    use std::sync::Mutex;
+
+    // the last pattern seen, compiled; `None` if it didn't compile
+    static CACHE: Mutex<Option<(String, Option<Regex>)>> = Mutex::new(None);
+
+    let pattern = match std::env::var("TMP_LOG_EXCLUDE_RE") {
+        Ok(x) if !x.is_empty() => x,
+        _ => return false,
+    };
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if !matches!(*cache, Some((ref p, _)) if *p == pattern) {
+        let re = Regex::new(&pattern).ok();
+        *cache = Some((pattern, re));
+    }
+    match *cache {
+        Some((_, Some(ref re))) => re.is_match(msg),
+        _ => false,
+    }
+}
+
+#[cfg(not(feature = "regex"))]
+fn excluded_re(_msg: &str) -> bool {
+    false
+}
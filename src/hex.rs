@@ -0,0 +1,36 @@
+//! The classic hexdump layout used by `tlog_hex!`.
+
+use std::fmt::Write as _;
+
+/// Sixteen bytes per line: offset, hex bytes in two groups of eight and
+/// an ASCII gutter.
+///
+/// ```text
+/// 00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 ff  |Hello, world!...|
+/// ```
+pub(crate) fn dump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let _ = write!(out, "{:08x} ", i * 16);
+        for j in 0..16 {
+            if j % 8 == 0 {
+                out.push(' ');
+            }
+            match chunk.get(j) {
+                Some(b) => {
+                    let _ = write!(out, "{:02x} ", b);
+                }
+                None => out.push_str("   "),
+            }
+        }
+        out.push_str(" |");
+        for &b in chunk {
+            out.push(if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' });
+        }
+        out.push('|');
+    }
+    out
+}
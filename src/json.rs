@@ -0,0 +1,44 @@
+//! Just enough JSON writing for `TMP_LOG_FORMAT=json`.
+
+use std::fmt::Write as _;
+
+/// Append `s` to `out` as a quoted JSON string.
+pub(crate) fn push_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Append `"key":"value"` to `out`, with a leading comma unless `out`
+/// is still just the opening brace.
+pub(crate) fn push_field(out: &mut String, key: &str, value: &str) {
+    push_key(out, key);
+    push_str(out, value);
+}
+
+/// Like `push_field`, for a value that is already valid JSON (a number
+/// or a boolean).
+pub(crate) fn push_raw_field(out: &mut String, key: &str, value: &str) {
+    push_key(out, key);
+    out.push_str(value);
+}
+
+fn push_key(out: &mut String, key: &str) {
+    if !out.ends_with('{') {
+        out.push(',');
+    }
+    push_str(out, key);
+    out.push(':');
+}
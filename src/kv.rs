@@ -0,0 +1,62 @@
+//! The values of `tlog_kv!` fields, which keep track of whether they are
+//! numbers or booleans so the JSON lines can write them unquoted.
+//!
+//! `tlog_kv!` calls the `__kv_value` method on `&KvWrap(&value)`: method
+//! lookup tries `KvTyped`, implemented for the wrapped numbers and
+//! booleans themselves, before `KvDisplay`, implemented one reference
+//! further for anything `Display`.
+
+use std::fmt;
+
+/// A field value: its `Display` text, and whether that text is also the
+/// JSON for it.
+#[doc(hidden)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KvValue {
+    pub(crate) text: String,
+    pub(crate) raw: bool,
+}
+
+#[doc(hidden)]
+pub struct KvWrap<'a, T: ?Sized>(pub &'a T);
+
+#[doc(hidden)]
+pub trait KvTyped {
+    fn __kv_value(&self) -> KvValue;
+}
+
+#[doc(hidden)]
+pub trait KvDisplay {
+    fn __kv_value(&self) -> KvValue;
+}
+
+macro_rules! typed {
+    ($($t:ty),*) => {$(
+        impl KvTyped for KvWrap<'_, $t> {
+            fn __kv_value(&self) -> KvValue {
+                KvValue { text: self.0.to_string(), raw: true }
+            }
+        }
+    )*};
+}
+
+typed!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, bool);
+
+macro_rules! float {
+    ($($t:ty),*) => {$(
+        // `Display` never uses an exponent, but `inf` and `NaN` are no JSON
+        impl KvTyped for KvWrap<'_, $t> {
+            fn __kv_value(&self) -> KvValue {
+                KvValue { text: self.0.to_string(), raw: self.0.is_finite() }
+            }
+        }
+    )*};
+}
+
+float!(f32, f64);
+
+impl<T: fmt::Display + ?Sized> KvDisplay for &KvWrap<'_, T> {
+    fn __kv_value(&self) -> KvValue {
+        KvValue { text: self.0.to_string(), raw: false }
+    }
+}
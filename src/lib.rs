@@ -10,89 +10,2824 @@ use tlog::tlog;
 tlog!("{} = {}", "5 x 7", 5 * 7);
 ```
 
-Logs will be written to file `/tmp/t.log` unless changed with env `TMP_LOG_FILE`.
+Logs will be written to file `t.log` in the temp directory (`/tmp/t.log`
+on Unix, `%TEMP%\t.log` on Windows) unless changed with env
+`TMP_LOG_FILE`.
 
-```
+```text
 $ cat /tmp/t.log
 [2022-09-05 11:10:31.763][15235] 5 x 7 = 35
 ```
+
+```
+std::env::remove_var("TMP_LOG_FILE");
+assert_eq!(tlog::log_file_path(), std::env::temp_dir().join("t.log"));
+```
+
+Missing parent directories of the log file are created. If
+`TMP_LOG_FILE` names a directory, a warning is printed to stderr once
+and the lines go to the default `t.log` instead.
+
+```
+# #[cfg(feature = "enabled")] {
+# let dir = std::env::temp_dir().join(format!("tlog-parent-{}", std::process::id()));
+let path = dir.join("a").join("b").join("t.log");
+std::env::set_var("TMP_LOG_FILE", &path);
+tlog::tlog!("nested");
+# assert!(std::fs::read_to_string(&path).unwrap().ends_with("] nested\n"));
+
+std::env::set_var("TMP_LOG_FILE", &dir);
+assert_eq!(tlog::log_file_path(), std::env::temp_dir().join("t.log"));
+# assert!(dir.is_dir());
+# std::fs::remove_dir_all(&dir).unwrap();
+# }
+```
+
+# Levels
+
+`tlog_error!`, `tlog_warn!`, `tlog_info!` and `tlog_debug!` work like
+`tlog!` but add a fixed-width level field:
+
+```text
+[2022-09-05 11:10:31.763][15235][WARN ] disk almost full
+```
+
+Set env `TMP_LOG_LEVEL` (e.g. `TMP_LOG_LEVEL=warn`) to drop the lines
+below that level. Lines from plain `tlog!` are always written.
+
+# Hostname
+
+Set env `TMP_LOG_HOST=1` to add the machine's name, for log files on a
+shared mount:
+
+```text
+[2022-09-05 11:10:31.763][15235][build-box] hello
+```
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-host-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+tlog::tlog!("plain");
+std::env::set_var("TMP_LOG_HOST", "1");
+tlog::tlog!("with host");
+# let content = std::fs::read_to_string(&path).unwrap();
+# let lines: Vec<_> = content.lines().collect();
+# let pid = std::process::id();
+# assert!(lines[0].ends_with(&format!("[{}] plain", pid)));
+# assert!(lines[1].contains(&format!("[{}][", pid)) && lines[1].ends_with("] with host"));
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+# Tags
+
+`tlog::set_tag("web")` (or env `TMP_LOG_TAG=web`) adds a `[web]` field
+to the lines that follow:
+
+```text
+[2022-09-05 11:10:31.763][15235][web] hello
+```
+
+Likewise `tlog::set_version("v1.2.3-abc")` (or env `TMP_LOG_VERSION`)
+adds a `[v1.2.3-abc]` field after it, to tell which build wrote a line.
+`tlog::tlog_set_version!()` uses the version from your `Cargo.toml`.
+
+# Threads
+
+Lines logged from several threads at once never mix: each one is
+written in one go while the others wait, however long it is.
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-interleave-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+let workers: Vec<_> = (0..8u8).map(|t| {
+    std::thread::spawn(move || {
+        let body = ((b'a' + t) as char).to_string().repeat(100_000);
+        for _ in 0..20 {
+            tlog::tlog!("{} {}", t, body);
+        }
+    })
+}).collect();
+for w in workers {
+    w.join().unwrap();
+}
+# let content = std::fs::read_to_string(&path).unwrap();
+# assert_eq!(content.lines().count(), 160);
+# for line in content.lines() {
+#     let msg = line.split("] ").nth(1).unwrap();
+#     let t: u8 = msg[..1].parse().unwrap();
+#     assert_eq!(msg.len(), 100_002);
+#     assert!(msg[2..].bytes().all(|c| c == b'a' + t));
+# }
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+Set env `TMP_LOG_THREAD=1` to add the name of the logging thread (or its
+`ThreadId` when it has no name):
+
+```text
+[2022-09-05 11:10:31.763][15235][main] hello
+```
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-thread-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::env::set_var("TMP_LOG_THREAD", "1");
+let workers: Vec<_> = ["alpha", "beta"].iter().map(|name| {
+    std::thread::Builder::new()
+        .name(name.to_string())
+        .spawn(|| tlog::tlog!("working"))
+        .unwrap()
+}).collect();
+for w in workers {
+    w.join().unwrap();
+}
+# let content = std::fs::read_to_string(&path).unwrap();
+# assert!(content.contains("][alpha] working"));
+# assert!(content.contains("][beta] working"));
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+# Sequence numbers
+
+Set env `TMP_LOG_SEQ=1` to number the lines, which orders lines that
+share a timestamp:
+
+```text
+[2022-09-05 11:10:31.763][15235][#000123] hello
+```
+
+The counter is shared by all threads, so every line gets its own number
+and each thread's numbers keep increasing:
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-seq-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::env::set_var("TMP_LOG_SEQ", "1");
+let workers: Vec<_> = (0..4).map(|t| {
+    std::thread::spawn(move || for i in 0..250 {
+        tlog::tlog!("thread {} line {}", t, i);
+    })
+}).collect();
+for w in workers {
+    w.join().unwrap();
+}
+# let content = std::fs::read_to_string(&path).unwrap();
+# let seq = |line: &str| -> u64 { line.split("[#").nth(1).unwrap()[..6].parse().unwrap() };
+# let mut all: Vec<u64> = content.lines().map(seq).collect();
+# for t in 0..4 {
+#     let tag = format!("] thread {} line", t);
+#     let mine: Vec<u64> = content.lines().filter(|l| l.contains(&tag)).map(seq).collect();
+#     assert_eq!(mine.len(), 250);
+#     assert!(mine.windows(2).all(|w| w[0] < w[1]));
+# }
+# all.sort();
+# all.dedup();
+# assert_eq!(all.len(), 1000);
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+# Call site
+
+Set env `TMP_LOG_LOC=1` to add the file and line of the macro call:
+
+```text
+[2022-09-05 11:10:31.763][15235] src/main.rs:42: hello
+```
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-loc-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::env::set_var("TMP_LOG_LOC", "1");
+tlog::tlog!("hello");
+# let content = std::fs::read_to_string(&path).unwrap();
+# assert!(content.contains(&format!("] {}:", file!())), "{}", content);
+# assert!(content.ends_with(": hello\n"));
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+Set env `TMP_LOG_MODULE=1` to add the module path of the macro call:
+
+```text
+[2022-09-05 11:10:31.763][15235][myapp::net::conn] connected
+```
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-module-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::env::set_var("TMP_LOG_MODULE", "1");
+mod net {
+    pub mod conn {
+        pub fn connect() {
+            tlog::tlog!("connected");
+#           tlog::tlogd!(1 + 1);
+#           tlog::tlog_assert!(1 > 2);
+        }
+    }
+}
+net::conn::connect();
+# let content = std::fs::read_to_string(&path).unwrap();
+# let lines: Vec<_> = content.lines().collect();
+# assert!(lines[0].ends_with("::net::conn] connected"), "{}", content);
+# assert!(lines[1].contains("::net::conn] ") && lines[1].ends_with(": 1 + 1 = 2"), "{}", content);
+# assert!(lines[2].contains("::net::conn][ERROR] ") && lines[2].ends_with(": ASSERT FAILED: 1 > 2"), "{}", content);
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+# Multiline messages
+
+By default a multiline message is written as is, so only its first line
+carries the header. Set env `TMP_LOG_MULTILINE=prefix` to repeat the
+header on every line, or `TMP_LOG_MULTILINE=indent` to line up the
+following lines under the first one:
+
+```text
+[2022-09-05 11:10:31.763][15235] line1
+                                 line2
+```
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-multiline-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::env::set_var("TMP_LOG_MULTILINE", "prefix");
+tlog::tlog!("line1\nline2");
+# let content = std::fs::read_to_string(&path).unwrap();
+# let lines: Vec<_> = content.lines().collect();
+# assert_eq!(lines.len(), 2);
+# assert!(lines[0].starts_with('[') && lines[0].ends_with("] line1"));
+# assert!(lines[1].starts_with('[') && lines[1].ends_with("] line2"));
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+# JSON
+
+Set env `TMP_LOG_FORMAT=json` to write one JSON object per line instead:
+
+```text
+{"ts":"2022-09-05 11:10:31.763","pid":15235,"msg":"5 x 7 = 35"}
+```
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-json-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::env::set_var("TMP_LOG_FORMAT", "json");
+tlog::tlog!("say \"hi\"\nback\\slash");
+# let content = std::fs::read_to_string(&path).unwrap();
+# assert_eq!(content.lines().count(), 1);
+# assert!(content.starts_with(r#"{"ts":""#));
+# assert!(content.ends_with("}\n"));
+# assert!(content.trim_end().ends_with(r#","msg":"say \"hi\"\nback\\slash"}"#));
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+# Long messages
+
+Set env `TMP_LOG_MAX_MSG` to a number of bytes to cut longer messages
+short, marked with how much was left out. The header isn't counted, and
+characters are never split.
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-max-msg-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::env::set_var("TMP_LOG_MAX_MSG", "10");
+tlog::tlog!("{}", "x".repeat(1000));
+tlog::tlog!("{}", "日本語のテキスト"); // 3 bytes each
+tlog::tlog!("short");
+# let content = std::fs::read_to_string(&path).unwrap();
+# let lines: Vec<_> = content.lines().collect();
+# assert!(lines[0].ends_with("] xxxxxxxxxx…[truncated 990 bytes]"));
+# assert!(lines[1].ends_with("] 日本語…[truncated 15 bytes]"));
+# assert!(lines[2].ends_with("] short"));
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+# Templates
+
+Set env `TMP_LOG_TEMPLATE` to lay the text lines out yourself, e.g.
+`{pid} {ts}: {msg}`. The placeholders are `{ts}`, `{pid}`, `{host}`,
+`{seq}`, `{tag}`, `{version}`, `{thread}`, `{level}`, `{file}`, `{line}`,
+`{module}` and `{msg}`; fields that a line doesn't have are left empty. Anything
+else, unknown placeholders included, is written as it is. Without a
+`{msg}`, the message goes at the end.
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-template-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::env::set_var("TMP_LOG_TEMPLATE", "{pid} {level} {ts}: {msg}");
+tlog::tlog_warn!("disk almost full");
+std::env::set_var("TMP_LOG_TEMPLATE", "{pid} {oops} {msg}");
+tlog::tlog!("hello");
+# let content = std::fs::read_to_string(&path).unwrap();
+# let lines: Vec<_> = content.lines().collect();
+# let pid = std::process::id();
+# assert!(lines[0].starts_with(&format!("{} WARN 20", pid)));
+# assert!(lines[0].ends_with(": disk almost full"));
+# assert_eq!(lines[1], format!("{} {{oops}} hello", pid));
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+# Filtering
+
+Set env `TMP_LOG_EXCLUDE` to a comma separated list of words to drop
+the lines whose message contains any of them. Only the message is
+looked at, not the header.
+
+With the `regex` feature, env `TMP_LOG_EXCLUDE_RE` drops the lines whose
+message matches that regular expression.
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-exclude-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::env::set_var("TMP_LOG_EXCLUDE", "heartbeat,poll");
+tlog::tlog!("heartbeat ok");
+tlog::tlog!("request done");
+tlog::tlog!("poll: nothing new");
+# #[cfg(feature = "regex")] {
+std::env::set_var("TMP_LOG_EXCLUDE_RE", r"^cache (hit|miss)");
+tlog::tlog!("cache hit for {}", 42);
+# }
+# let content = std::fs::read_to_string(&path).unwrap();
+# assert_eq!(content.lines().count(), 1);
+# assert!(content.ends_with("] request done\n"));
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+# Stderr
+
+Set env `TMP_LOG_STDERR=1` to also print every line to stderr. The file
+still gets the line, whether or not writing to stderr works.
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-stderr-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::env::set_var("TMP_LOG_STDERR", "1");
+tlog::tlog!("seen twice");
+# assert!(std::fs::read_to_string(&path).unwrap().ends_with("] seen twice\n"));
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+When stderr is a terminal the level field is colored there, `WARN` in
+yellow, `ERROR` in red and so on. Set env `NO_COLOR` to turn that off,
+or `TMP_LOG_COLOR=1` to color even when stderr is not a terminal. The
+file never gets the colors.
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-color-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::env::set_var("TMP_LOG_STDERR", "1");
+std::env::set_var("TMP_LOG_COLOR", "1");
+tlog::tlog_warn!("careful");
+tlog::tlog_error!("failed\nbadly");
+let content = std::fs::read_to_string(&path).unwrap();
+assert!(content.contains("[WARN ] careful"));
+assert!(!content.contains('\x1b'));
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+# Buffering
+
+Set env `TMP_LOG_BUFFERED=1` to collect lines in memory and have a
+background thread write them every 100ms. Call `tlog::flush()`, or hold
+the guard of `tlog::init()` (or `tlog::flush_guard()`) in `main`, to make
+sure the last ones reach the file before the program exits.
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-buffered-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::env::set_var("TMP_LOG_BUFFERED", "1");
+{
+    let _guard = tlog::flush_guard();
+    for i in 0..50_000 {
+        tlog::tlog!("line {}", i);
+    }
+}
+# let content = std::fs::read_to_string(&path).unwrap();
+# assert_eq!(content.lines().count(), 50_000);
+# assert!(content.ends_with("] line 49999\n"));
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+Set env `TMP_LOG_DROP=1` as well to never wait for the disk: once more
+than `TMP_LOG_BUFFER_BYTES` (default 1 MiB) are waiting, new lines are
+dropped, and a `dropped N messages` line is written with the lines that
+made it.
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-drop-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::env::set_var("TMP_LOG_BUFFERED", "1");
+std::env::set_var("TMP_LOG_DROP", "1");
+std::env::set_var("TMP_LOG_BUFFER_BYTES", "4096");
+for i in 0..10_000 {
+    tlog::tlog!("flood {}", i);
+}
+tlog::flush().unwrap();
+# let content = std::fs::read_to_string(&path).unwrap();
+# let dropped: usize = content.lines()
+#     .filter_map(|l| l.split("] dropped ").nth(1))
+#     .map(|n| n.split(' ').next().unwrap().parse::<usize>().unwrap())
+#     .sum();
+# let written = content.lines().filter(|l| l.contains("] flood ")).count();
+# assert!(dropped > 0);
+# assert_eq!(dropped + written, 10_000);
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+# Truncating
+
+Set env `TMP_LOG_TRUNCATE=1` to empty the file on the first line a
+process writes, so it only has the lines of the current run.
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-truncate-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::fs::write(&path, "from the last run\n").unwrap();
+std::env::set_var("TMP_LOG_TRUNCATE", "1");
+tlog::tlog!("first");
+tlog::tlog!("second");
+# let content = std::fs::read_to_string(&path).unwrap();
+# assert!(!content.contains("from the last run"));
+# assert_eq!(content.lines().count(), 2);
+# assert!(content.ends_with("] second\n"));
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+# Mirroring
+
+Set env `TMP_LOG_MIRROR` to the path of a second file to write every
+line to as well, e.g. a persistent copy of a log kept on a tmpfs. The
+log file is written even when the mirror can't be.
+
+```
+# #[cfg(feature = "enabled")] {
+# let dir = std::env::temp_dir().join(format!("tlog-mirror-{}", std::process::id()));
+# std::fs::create_dir_all(&dir).unwrap();
+# std::env::set_var("TMP_LOG_FILE", dir.join("t.log"));
+std::env::set_var("TMP_LOG_MIRROR", dir.join("copy.log"));
+tlog::tlog!("twice");
+std::env::set_var("TMP_LOG_MIRROR", dir.join("missing").join("copy.log"));
+tlog::tlog!("once");
+# let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+# assert_eq!(read("copy.log").lines().count(), 1);
+# assert!(read("copy.log").ends_with("] twice\n"));
+# assert!(read("t.log").contains("] twice\n"));
+# assert!(read("t.log").ends_with("] once\n"));
+# std::fs::remove_dir_all(&dir).unwrap();
+# }
+```
+
+# Durability
+
+Set env `TMP_LOG_SYNC=1` to sync the file to disk after every write, so
+the lines survive a crash of the whole machine. It makes logging much
+slower, so only turn it on while chasing such a crash.
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-sync-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::env::set_var("TMP_LOG_SYNC", "1");
+tlog::tlog!("on disk");
+# assert!(std::fs::read_to_string(&path).unwrap().ends_with("] on disk\n"));
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+# Disabling
+
+Logging is behind the default `enabled` feature. Build with
+`default-features = false` and every `tlog*!` call compiles away: no
+formatting, no file. `tlogd!` still evaluates and returns its value.
+
+```
+# let path = std::env::temp_dir().join(format!("tlog-disabled-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+for i in 0..1000 {
+    tlog::tlog!("line {}", i);
+}
+assert_eq!(tlog::tlogd!(5 * 7), 35);
+{
+    let _t = tlog::tlog_timer!("work {}", 1);
+#   #[cfg(not(feature = "enabled"))]
+#   assert_eq!(_t.elapsed(), std::time::Duration::ZERO);
+}
+# #[cfg(not(feature = "enabled"))]
+# assert!(!path.exists());
+# let _ = std::fs::remove_file(&path);
+```
+
+# Timestamps
+
+Timestamps have millisecond precision by default. Set env
+`TMP_LOG_PRECISION` to `us` or `ns` for micro- or nanoseconds.
+
+They are in the local time, without an offset. Set env `TMP_LOG_TZ=1`
+to append it, like `2022-09-05 11:10:31.763+08:00` (or `Z` for UTC).
+
+Set env `TMP_LOG_CLOCK=mono` to write the seconds since the process
+started logging instead, from the monotonic clock. They never go back,
+whatever NTP or daylight saving time do to the wall clock:
+
+```text
+[12.034][15235] hello
+```
+
+```
+# #[cfg(feature = "enabled")] {
+# let path = std::env::temp_dir().join(format!("tlog-mono-{}.log", std::process::id()));
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::env::set_var("TMP_LOG_CLOCK", "mono");
+tlog::tlog!("first");
+std::thread::sleep(std::time::Duration::from_millis(5));
+tlog::tlog!("second");
+# let content = std::fs::read_to_string(&path).unwrap();
+# let secs: Vec<f64> = content.lines().map(|l| l[1..l.find(']').unwrap()].parse().unwrap()).collect();
+# assert_eq!(secs.len(), 2);
+# assert!(secs[0] <= secs[1] && secs[1] - secs[0] >= 0.004);
+# std::fs::remove_file(&path).unwrap();
+# }
+```
+
+# Rotation
+
+Set env `TMP_LOG_MAX_BYTES` to rotate the log file once it would grow
+past that many bytes: `t.log` becomes `t.log.1`, `t.log.1` becomes
+`t.log.2` and so on, keeping `TMP_LOG_MAX_FILES` (default 3) old files.
+
+With the `gzip` feature, set env `TMP_LOG_GZIP=1` to compress the
+rotated files to `t.log.1.gz` etc. The current `t.log` is never
+compressed.
+
+```
+# #[cfg(all(feature = "gzip", feature = "enabled"))] {
+# let dir = std::env::temp_dir().join(format!("tlog-gzip-{}", std::process::id()));
+# std::fs::create_dir_all(&dir).unwrap();
+# let path = dir.join("t.log");
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::env::set_var("TMP_LOG_MAX_BYTES", "400");
+std::env::set_var("TMP_LOG_GZIP", "1");
+for i in 0..4 {
+    tlog::tlog!("line number {} {}", i, "-".repeat(120));
+}
+# use std::io::Read;
+# let gz = std::fs::File::open(dir.join("t.log.1.gz")).unwrap();
+# let mut old = String::new();
+# flate2::read::MultiGzDecoder::new(gz).read_to_string(&mut old).unwrap();
+# assert!(old.contains("line number 0 ") && old.contains("line number 1 "));
+# assert!(!dir.join("t.log.1").exists());
+# assert!(std::fs::read_to_string(&path).unwrap().contains("line number 3 "));
+# std::fs::remove_dir_all(&dir).unwrap();
+# }
+```
+
+Set env `TMP_LOG_DAILY=1` to start a new file each day instead (or as
+well): on the first line of a new day, the file is renamed to
+`t.log.YYYY-MM-DD` after the day it was written on.
+
+```
+# #[cfg(feature = "enabled")] {
+# let dir = std::env::temp_dir().join(format!("tlog-daily-{}", std::process::id()));
+# std::fs::create_dir_all(&dir).unwrap();
+# let path = dir.join("t.log");
+# std::env::set_var("TMP_LOG_FILE", &path);
+// pretend the file was last written two days ago
+std::fs::write(&path, "old line\n").unwrap();
+let then = std::time::SystemTime::now() - std::time::Duration::from_secs(2 * 86400);
+std::fs::File::options().write(true).open(&path).unwrap().set_modified(then).unwrap();
+
+std::env::set_var("TMP_LOG_DAILY", "1");
+tlog::tlog!("new day");
+# let secs = then.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64;
+# let day = tlog::DateTime::from_unix_timestamp(secs).unwrap().date();
+# let dated = dir.join(format!("t.log.{}", day));
+# assert_eq!(std::fs::read_to_string(&dated).unwrap(), "old line\n");
+# assert!(std::fs::read_to_string(&path).unwrap().ends_with("] new day\n"));
+# std::fs::remove_dir_all(&dir).unwrap();
+# }
+```
+
+```
+# #[cfg(feature = "enabled")] {
+# let dir = std::env::temp_dir().join(format!("tlog-rotate-{}", std::process::id()));
+# std::fs::create_dir_all(&dir).unwrap();
+# let path = dir.join("t.log");
+# std::env::set_var("TMP_LOG_FILE", &path);
+std::env::set_var("TMP_LOG_MAX_BYTES", "400");
+std::env::set_var("TMP_LOG_MAX_FILES", "2");
+for i in 0..10 {
+    // ~160 bytes per line, so two lines fit in each file
+    tlog::tlog!("line number {} {}", i, "-".repeat(120));
+}
+# let read = |p: &std::path::Path| std::fs::read_to_string(p).unwrap();
+# let rotated = |n: u32| dir.join(format!("t.log.{}", n));
+# assert!(read(&path).contains("line number 9 "));
+# assert!(read(&rotated(1)).contains("line number 7 "));
+# assert!(read(&rotated(2)).contains("line number 5 "));
+# assert!(!rotated(3).exists());
+# std::fs::remove_dir_all(&dir).unwrap();
+# }
+```
 */
 
-use std::fmt;
-use time::OffsetDateTime;
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, Once, OnceLock, RwLock};
+use std::thread::LocalKey;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "tokio")]
+mod alog;
+mod batch;
+#[cfg(feature = "binary")]
+mod binary;
+mod buffer;
+mod datetime;
+mod filter;
+mod hex;
+mod json;
+mod kv;
+#[cfg(feature = "log")]
+mod log_backend;
+mod logger;
+mod parse;
+mod rotate;
+mod tail;
+mod template;
+#[cfg(feature = "tracing")]
+mod tracing_layer;
+
+#[cfg(feature = "tokio")]
+pub use alog::alog;
+#[doc(hidden)]
+pub use batch::{__batch, BatchGuard};
+#[doc(hidden)]
+pub use kv::{KvDisplay, KvTyped, KvValue, KvWrap};
+#[cfg(feature = "binary")]
+pub use binary::{bin_file_path, read_bin, BinRecords, LogRecord};
+pub use datetime::{DateTime, ParseError, Precision};
+#[cfg(feature = "test-util")]
+pub use datetime::{reset_clock_for_test, set_clock_for_test};
+#[cfg(feature = "log")]
+pub use log_backend::{init_log_backend, TlogLogger};
+pub use logger::{Format, Logger};
+pub use parse::{parse_line, LogEntry};
+pub use tail::{tail, Tail};
+
+use logger::{Config, Multiline};
+#[cfg(feature = "tracing")]
+pub use tracing_layer::TlogLayer;
+
+/// Whether the `enabled` feature is on. The macros test this constant
+/// before formatting anything, so with the feature off they compile down
+/// to nothing.
+#[doc(hidden)]
+pub const __ENABLED: bool = cfg!(feature = "enabled");
+
+/// The id of the current process, as written in the `[pid]` field.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-pid-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// tlog::tlog!("hello");
+/// let content = std::fs::read_to_string(&path).unwrap();
+/// let pid = content.split("][").nth(1).unwrap();
+/// assert_eq!(pid.split(']').next().unwrap(), std::process::id().to_string());
+/// assert_eq!(tlog::getpid() as u32, std::process::id());
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+pub fn getpid() -> i32 {
+    std::process::id() as i32
+}
+
+/// Severity of a log line written by `tlog_error!`, `tlog_warn!`,
+/// `tlog_info!` and `tlog_debug!`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        }
+    }
+
+    /// The ANSI color of the level field on a terminal.
+    fn color(&self) -> &'static str {
+        match self {
+            Level::Error => "\x1b[31m",
+            Level::Warn => "\x1b[33m",
+            Level::Info => "\x1b[32m",
+            Level::Debug => "\x1b[36m",
+        }
+    }
+
+    /// Parse a level name such as `warn` or `INFO`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "error" => Some(Level::Error),
+            "warn" | "warning" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// The level set with env `TMP_LOG_LEVEL`; lines below it are dropped.
+/// Unset (or unknown) means every level is written.
+fn max_level() -> Option<Level> {
+    std::env::var("TMP_LOG_LEVEL").ok().and_then(|x| Level::from_name(&x))
+}
+
+/// Whether a `level` line passes `TMP_LOG_LEVEL`, for the `log` and
+/// `tracing` backends to check before formatting.
+#[cfg(any(feature = "log", feature = "tracing"))]
+fn level_enabled(level: Level) -> bool {
+    max_level().is_none_or(|max| level <= max)
+}
+
+/// Whether an on/off env such as `TMP_LOG_THREAD=1` is switched on.
+fn env_flag(name: &str) -> bool {
+    match std::env::var(name) {
+        Ok(x) => !x.is_empty() && x != "0",
+        Err(_) => false,
+    }
+}
+
+/// The machine's name, read once from `/etc/hostname` or env
+/// `HOSTNAME`/`COMPUTERNAME`.
+fn hostname() -> &'static str {
+    static HOSTNAME: OnceLock<String> = OnceLock::new();
+    HOSTNAME.get_or_init(|| {
+        let from_file = std::fs::read_to_string("/etc/hostname").ok();
+        let from_env = || std::env::var("HOSTNAME").or_else(|_| std::env::var("COMPUTERNAME")).ok();
+        from_file
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .or_else(from_env)
+            .filter(|x| !x.is_empty())
+            .unwrap_or_else(|| "unknown".to_string())
+    })
+}
+
+/// The current thread's name, or its id for unnamed threads.
+fn thread_name() -> String {
+    let thread = std::thread::current();
+    match thread.name() {
+        Some(name) => name.to_string(),
+        None => format!("{:?}", thread.id()),
+    }
+}
+
+/// `t.log` in the temp directory: `/tmp/t.log` on Unix.
+fn default_log_file() -> String {
+    std::env::temp_dir().join("t.log").to_string_lossy().into_owned()
+}
+
+/// The log file the macros write to: env `TMP_LOG_FILE` if it is set and
+/// not empty, else `t.log` in the temp directory. As for the macros, a
+/// `TMP_LOG_FILE` naming a directory gives the default file. This creates
+/// nothing; missing parent directories are made when a line is written.
+///
+/// ```
+/// # let dir = std::env::temp_dir().join(format!("tlog-path-{}", std::process::id()));
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::env::remove_var("TMP_LOG_FILE");
+/// assert_eq!(tlog::log_file_path(), std::env::temp_dir().join("t.log"));
+/// std::env::set_var("TMP_LOG_FILE", "");
+/// assert_eq!(tlog::log_file_path(), std::env::temp_dir().join("t.log"));
+/// std::env::set_var("TMP_LOG_FILE", dir.join("x").join("mine.log"));
+/// assert_eq!(tlog::log_file_path(), dir.join("x").join("mine.log"));
+/// assert!(!dir.join("x").exists());
+/// std::env::set_var("TMP_LOG_FILE", &dir);
+/// assert_eq!(tlog::log_file_path(), std::env::temp_dir().join("t.log"));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn log_file_path() -> PathBuf {
+    PathBuf::from(log_file())
+}
+
+/// The log file as written to, see `log_file_path`.
+fn log_file() -> String {
+    let path = match std::env::var("TMP_LOG_FILE") {
+        Ok(x) if !x.is_empty() => x,
+        _ => default_log_file(),
+    };
+    checked_path(path)
+}
+
+/// Open `path` for appending, creating it and any missing parent
+/// directories first.
+pub(crate) fn open_append(path: impl AsRef<Path>) -> io::Result<File> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent().filter(|d| !d.as_os_str().is_empty() && !d.exists()) {
+        // if this fails, so will the open, and the line is lost
+        let _ = std::fs::create_dir_all(parent);
+    }
+    OpenOptions::new().append(true).create(true).open(path)
+}
+
+/// `path` if a log file can be written there, else the default one.
+///
+/// A path naming a directory is replaced, with a warning on stderr the
+/// first time. Only the last path is remembered as checked, which is all
+/// the single log file needs.
+fn checked_path(path: String) -> String {
+    static CHECKED: Mutex<Option<(String, String)>> = Mutex::new(None);
+    static WARN: Once = Once::new();
+
+    let mut checked = CHECKED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((ref asked, ref usable)) = *checked {
+        if *asked == path {
+            return usable.clone();
+        }
+    }
+    let p = Path::new(&path);
+    let usable = if p.is_dir() {
+        let default = default_log_file();
+        WARN.call_once(|| {
+            eprintln!("tlog: log file {} is a directory, writing to {} instead", path, default);
+        });
+        default
+    } else {
+        path.clone()
+    };
+    *checked = Some((path, usable.clone()));
+    usable
+}
+
+/// The `[tag]` field, seeded from env `TMP_LOG_TAG` on first use.
+fn tag_lock() -> &'static RwLock<Option<String>> {
+    static TAG: OnceLock<RwLock<Option<String>>> = OnceLock::new();
+    TAG.get_or_init(|| {
+        let tag = std::env::var("TMP_LOG_TAG").ok().filter(|x| !x.is_empty());
+        RwLock::new(tag)
+    })
+}
+
+/// Tag the following lines of this process with `[tag]`, e.g. to tell
+/// components sharing one log file apart. An empty tag removes it.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-tag-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// tlog::set_tag("web");
+/// tlog::tlog!("tagged");
+/// tlog::set_tag("");
+/// tlog::tlog!("plain");
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # let lines: Vec<_> = content.lines().collect();
+/// # assert!(lines[0].ends_with("][web] tagged"));
+/// # assert!(!lines[1].contains("web") && lines[1].ends_with("] plain"));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+pub fn set_tag(tag: impl Into<String>) {
+    let tag = tag.into();
+    *tag_lock().write().unwrap_or_else(|e| e.into_inner()) = if tag.is_empty() { None } else { Some(tag) };
+}
+
+fn tag() -> Option<String> {
+    tag_lock().read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// The `[version]` field, seeded from env `TMP_LOG_VERSION` on first use.
+fn version_lock() -> &'static RwLock<Option<String>> {
+    static VERSION: OnceLock<RwLock<Option<String>>> = OnceLock::new();
+    VERSION.get_or_init(|| {
+        let version = std::env::var("TMP_LOG_VERSION").ok().filter(|x| !x.is_empty());
+        RwLock::new(version)
+    })
+}
+
+/// Add a `[version]` field, e.g. a git commit, to the following lines
+/// so they tell which build wrote them. An empty version removes it.
+/// `tlog_set_version!()` sets it to the version of the calling crate.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-version-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// tlog::set_version("v1.2.3-abc");
+/// tlog::tlog!("built");
+/// tlog::tlog_set_version!();
+/// tlog::tlog!("from cargo");
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # let lines: Vec<_> = content.lines().collect();
+/// # assert!(lines[0].ends_with("][v1.2.3-abc] built"));
+/// # assert!(lines[1].ends_with(&format!("][{}] from cargo", env!("CARGO_PKG_VERSION"))));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+pub fn set_version(version: impl Into<String>) {
+    let version = version.into();
+    *version_lock().write().unwrap_or_else(|e| e.into_inner()) = if version.is_empty() { None } else { Some(version) };
+}
+
+fn version() -> Option<String> {
+    version_lock().read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// The files registered with `route`, by target prefix.
+fn routes() -> &'static RwLock<HashMap<String, String>> {
+    static ROUTES: OnceLock<RwLock<HashMap<String, String>>> = OnceLock::new();
+    ROUTES.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Send the lines of `tlog_target!` calls whose target is `prefix`, or
+/// starts with `prefix::`, to `path` instead of the log file. When
+/// several prefixes match, the longest one wins; targets matching none
+/// go to the log file as usual.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let dir = std::env::temp_dir().join(format!("tlog-route-{}", std::process::id()));
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// # std::env::set_var("TMP_LOG_FILE", dir.join("t.log"));
+/// use tlog::tlog_target;
+///
+/// tlog::route("net", dir.join("net.log"));
+/// tlog_target!("net::http", "GET {}", "/");
+/// tlog_target!("db", "connected");
+/// # let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+/// # assert!(read("net.log").ends_with("] GET /\n"));
+/// # assert!(!read("t.log").contains("GET"));
+/// # assert!(read("t.log").ends_with("] connected\n"));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// # }
+/// ```
+pub fn route(prefix: impl Into<String>, path: impl AsRef<Path>) {
+    let path = path.as_ref().to_string_lossy().into_owned();
+    routes().write().unwrap_or_else(|e| e.into_inner()).insert(prefix.into(), path);
+}
+
+/// The file routed for `target`, if any.
+fn routed(target: &str) -> Option<String> {
+    let routes = routes().read().unwrap_or_else(|e| e.into_inner());
+    routes
+        .iter()
+        .filter(|(prefix, _)| {
+            target.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+        })
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, path)| path.clone())
+}
+
+/// Source of the `[#000123]` numbers written with `TMP_LOG_SEQ=1`. They
+/// are unique across threads and increase with each line.
+static SEQ: AtomicU64 = AtomicU64::new(1);
+
+/// The log file kept open between calls, with the path it was opened for.
+static LOG_FILE: Mutex<Option<(String, File)>> = Mutex::new(None);
+
+fn lock_log_file() -> std::sync::MutexGuard<'static, Option<(String, File)>> {
+    LOG_FILE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The day of the last write with `TMP_LOG_DAILY=1`, and to which path.
+static LAST_DAY: Mutex<Option<(String, time::Date)>> = Mutex::new(None);
+
+/// The mirror file kept open between calls, with its path.
+static MIRROR: Mutex<Option<(String, File)>> = Mutex::new(None);
+
+/// Append `data` to `path`, and to the mirror file of env
+/// `TMP_LOG_MIRROR` if there is one. Only errors writing `path` are
+/// returned: the mirror is a copy and never rotated, and failing to
+/// write it doesn't keep `path` from being written.
+fn write_to_file(path: &str, data: &[u8]) -> io::Result<()> {
+    let result = attempt(Some(path), || write_cached(path, data));
+    if let Ok(mirror) = std::env::var("TMP_LOG_MIRROR") {
+        if !mirror.is_empty() {
+            let _ = attempt(Some(&mirror), || write_mirror(&mirror, data));
+        }
+    }
+    result
+}
+
+fn write_mirror(path: &str, data: &[u8]) -> io::Result<()> {
+    let mut cached = MIRROR.lock().unwrap_or_else(|e| e.into_inner());
+    let reopen = match *cached {
+        Some((ref p, _)) => p != path || !Path::new(path).exists(),
+        None => true,
+    };
+    if reopen {
+        *cached = None;
+        let file = open_append(path)?;
+        *cached = Some((path.to_string(), file));
+    }
+    match *cached {
+        Some((_, ref mut file)) => file.write_all(data),
+        None => Ok(()),
+    }
+}
+
+/// Append `data` to `path`, reusing the cached handle when possible.
+///
+/// The file is reopened when `TMP_LOG_FILE` now resolves to a different
+/// path, or when the file was removed behind our back (`rm /tmp/t.log`
+/// is how most people reset it).
+///
+/// With `TMP_LOG_TRUNCATE=1`, the first call of the process empties the
+/// file before writing.
+///
+/// With `TMP_LOG_DAILY=1`, the file is moved aside first if it was last
+/// written on another day. With `TMP_LOG_MAX_BYTES` set, it is rotated
+/// first if `data` would push it past the threshold.
+fn write_cached(path: &str, data: &[u8]) -> io::Result<()> {
+    let mut cached = lock_log_file();
+    let mut reopen = match *cached {
+        Some((ref p, _)) => p != path || !Path::new(path).exists(),
+        None => true,
+    };
+    static TRUNCATE: Once = Once::new();
+    let mut truncate = false;
+    TRUNCATE.call_once(|| truncate = env_flag("TMP_LOG_TRUNCATE"));
+    if truncate {
+        *cached = None;
+        File::create(path)?;
+        reopen = true;
+    }
+    if rotate::daily() {
+        let today = DateTime::now().date();
+        let mut last = LAST_DAY.lock().unwrap_or_else(|e| e.into_inner());
+        let day = match *last {
+            Some((ref p, day)) if p == path => Some(day),
+            _ => rotate::file_day(path),
+        };
+        if let Some(day) = day.filter(|&day| day != today) {
+            *cached = None;
+            rotate::rotate_daily(path, day)?;
+            reopen = true;
+        }
+        *last = Some((path.to_string(), today));
+    }
+    if let Some(max_bytes) = rotate::max_bytes() {
+        let len = match *cached {
+            Some((_, ref file)) if !reopen => file.metadata()?.len(),
+            _ => std::fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+        };
+        if rotate::needs_rotation(len, data.len(), max_bytes) {
+            *cached = None;
+            rotate::rotate(path, rotate::max_files())?;
+            reopen = true;
+        }
+    }
+    if reopen {
+        *cached = None;
+        let file = open_append(path)?;
+        *cached = Some((path.to_string(), file));
+    }
+    match *cached {
+        Some((_, ref mut file)) => {
+            file.write_all(data)?;
+            sync(file)
+        }
+        None => Ok(()),
+    }
+}
+
+/// With env `TMP_LOG_SYNC=1`, wait for the data just written to reach the
+/// disk, so the last lines before a hard kill or power loss are not lost
+/// in the page cache.
+///
+/// This costs a disk round trip for every line, easily hundreds of times
+/// slower than a plain write, so it is off by default. Combined with
+/// `TMP_LOG_BUFFERED` it is paid once per flush rather than once per line.
+fn sync(file: &File) -> io::Result<()> {
+    if env_flag("TMP_LOG_SYNC") {
+        file.sync_data()?;
+    }
+    Ok(())
+}
+
+type Writer = Box<dyn Write + Send>;
+
+/// The sink installed with `set_writer`, used instead of the log file.
+static WRITER: Mutex<Option<Writer>> = Mutex::new(None);
+
+fn lock_writer() -> std::sync::MutexGuard<'static, Option<Writer>> {
+    WRITER.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Send all following lines to `w` instead of the log file, until
+/// `clear_writer` is called.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// use std::io::{self, Write};
+/// use std::sync::{Arc, Mutex};
+///
+/// #[derive(Clone, Default)]
+/// struct Captured(Arc<Mutex<Vec<u8>>>);
+///
+/// impl Write for Captured {
+///     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+///         self.0.lock().unwrap().write(buf)
+///     }
+///     fn flush(&mut self) -> io::Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// let captured = Captured::default();
+/// tlog::set_writer(Box::new(captured.clone()));
+/// tlog::tlog!("5 x 7 = {}", 5 * 7);
+/// tlog::clear_writer();
+///
+/// let out = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+/// assert!(out.starts_with('['));
+/// assert!(out.ends_with(&format!("][{}] 5 x 7 = 35\n", std::process::id())));
+/// # }
+/// ```
+pub fn set_writer(w: Box<dyn Write + Send>) {
+    *lock_writer() = Some(w);
+    // a new sink gets tried right away
+    lock_retry_at().retain(|r| r.0.is_some());
+}
+
+/// Go back to writing the log file after `set_writer`.
+pub fn clear_writer() {
+    *lock_writer() = None;
+    lock_retry_at().retain(|r| r.0.is_some());
+}
+
+/// Write out the lines buffered with `TMP_LOG_BUFFERED=1`, then flush
+/// the writer set with `set_writer` and the cached log file handle.
+pub fn flush() -> io::Result<()> {
+    guarded(flush_now)
+}
+
+fn flush_now() -> io::Result<()> {
+    buffer::flush()?;
+    if let Some(ref mut w) = *lock_writer() {
+        w.flush()?;
+    }
+    match *lock_log_file() {
+        Some((_, ref mut file)) => file.flush(),
+        None => Ok(()),
+    }
+}
+
+/// The whole log file, after writing out the buffered lines. A missing
+/// file reads as empty. Handy for checking what a test logged:
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-read-all-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// tlog::tlog!("one");
+/// tlog::tlog!("two");
+/// let content = tlog::read_all().unwrap();
+/// assert_eq!(content.lines().count(), 2);
+/// assert!(content.ends_with("] two\n"));
+///
+/// tlog::clear().unwrap();
+/// assert_eq!(tlog::read_all().unwrap(), "");
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+pub fn read_all() -> io::Result<String> {
+    buffer::flush()?;
+    // no line is half written while the file's lock is held
+    let _cached = lock_log_file();
+    match std::fs::read_to_string(log_file()) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        result => result,
+    }
+}
+
+/// Empty the log file, buffered lines included, see `read_all`.
+pub fn clear() -> io::Result<()> {
+    buffer::flush()?;
+    let _cached = lock_log_file();
+    match OpenOptions::new().write(true).open(log_file()) {
+        Ok(file) => file.set_len(0),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Write `msg` to the log file with the usual `[ts][pid]` header,
+/// returning any error opening or writing the file. For a second after
+/// such an error, lines for the same file are dropped without trying,
+/// and return an error too, see `write_failures`.
+///
+/// This is what `tlog!` calls, minus the error swallowing:
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// // a file can't be the parent directory of the log file
+/// let file = std::env::temp_dir().join(format!("tlog-not-a-dir-{}", std::process::id()));
+/// std::fs::write(&file, "").unwrap();
+/// std::env::set_var("TMP_LOG_FILE", file.join("t.log"));
+/// assert!(tlog::log_line("hello").is_err());
+/// assert!(tlog::log_line("again").is_err());
+/// # std::fs::remove_file(&file).unwrap();
+/// # }
+/// ```
+pub fn log_line(msg: &str) -> io::Result<()> {
+    write_line(None, None, msg)
+}
+
+/// Where a macro was invoked, filled in by `file!()`/`line!()` and
+/// `module_path!()`.
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy)]
+pub struct Site {
+    pub file: &'static str,
+    pub line: u32,
+    pub module: &'static str,
+}
+
+fn write_line(level: Option<Level>, site: Option<Site>, msg: &str) -> io::Result<()> {
+    write_line_with(level, site, msg, &[], None, None)
+}
+
+/// Like `write_line`, with `key=value` fields after the message, `mode`
+/// overriding `TMP_LOG_MULTILINE` and the file picked by `route` for
+/// `target`.
+fn write_line_with(
+    level: Option<Level>,
+    site: Option<Site>,
+    msg: &str,
+    fields: &[(&str, KvValue)],
+    mode: Option<Multiline>,
+    target: Option<&str>,
+) -> io::Result<()> {
+    if !__ENABLED {
+        return Ok(());
+    }
+    let mut config = Config::from_env();
+    if let Some(mode) = mode {
+        config.multiline = mode;
+    }
+    write_line_config(config, level, site, msg, fields, target)
+}
+
+/// Like `write_line_with`, with the settings of `config` rather than
+/// those of the environment.
+fn write_line_config(
+    config: Config,
+    level: Option<Level>,
+    site: Option<Site>,
+    msg: &str,
+    fields: &[(&str, KvValue)],
+    target: Option<&str>,
+) -> io::Result<()> {
+    if !config.enabled(level) || filter::excluded(msg) {
+        return Ok(());
+    }
+    close_raw(target);
+    with_buffer(&LINE, |line| guarded(|| {
+        config.render_into(line, level, site, msg, fields, &SEQ);
+        if env_flag("TMP_LOG_STDERR") {
+            let stderr = io::stderr();
+            // best effort: the file is what matters
+            let _ = match level {
+                Some(level) if config.format == Format::Text && color(&stderr) => {
+                    stderr.lock().write_all(paint(line, level).as_bytes())
+                }
+                _ => stderr.lock().write_all(line.as_bytes()),
+            };
+        }
+        remember(line);
+        write_out(target, line.as_bytes())
+    }))
+}
+
+thread_local! {
+    /// Set while the thread is in tlog's own writing code, maybe holding
+    /// its locks.
+    static WRITING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run `f` as tlog's own writing, see `writing`.
+fn guarded<R>(f: impl FnOnce() -> R) -> R {
+    struct Reset(bool);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            let _ = WRITING.try_with(|w| w.set(self.0));
+        }
+    }
+    let _reset = Reset(WRITING.try_with(|w| w.replace(true)).unwrap_or(false));
+    f()
+}
+
+/// Whether the thread is in tlog's own writing code: a panic there must
+/// not be logged, taking the locks it may hold again would deadlock.
+fn writing() -> bool {
+    WRITING.try_with(Cell::get).unwrap_or(false)
+}
+
+thread_local! {
+    /// The buffers the message and the line of the thread's current
+    /// `tlog!` are formatted into, kept to save allocations.
+    static MSG: Cell<String> = const { Cell::new(String::new()) };
+    static LINE: Cell<String> = const { Cell::new(String::new()) };
+}
+
+/// Run `f` with the emptied `buf`, taking it out for the time being: a
+/// line logged while formatting another one (from a `Display` impl, say)
+/// or during thread exit just gets a new `String`. Buffers grown past
+/// 64 KiB by a huge message are not kept.
+fn with_buffer<R>(buf: &'static LocalKey<Cell<String>>, f: impl FnOnce(&mut String) -> R) -> R {
+    let mut s = buf.try_with(Cell::take).unwrap_or_default();
+    s.clear();
+    let result = f(&mut s);
+    if s.capacity() <= 64 * 1024 {
+        let _ = buf.try_with(|buf| buf.set(s));
+    }
+    result
+}
+
+/// Whether to color the lines on stderr: only when it is a terminal, or
+/// forced with env `TMP_LOG_COLOR=1`, and never with env `NO_COLOR` set.
+fn color(stderr: &io::Stderr) -> bool {
+    if std::env::var_os("NO_COLOR").is_some_and(|x| !x.is_empty()) {
+        return false;
+    }
+    env_flag("TMP_LOG_COLOR") || stderr.is_terminal()
+}
+
+/// `line` with the level field of each of its lines colored.
+fn paint(line: &str, level: Level) -> String {
+    let field = format!("[{:<5}]", level);
+    let colored = format!("[{}{:<5}\x1b[0m]", level.color(), level);
+    line.split_inclusive('\n').map(|part| part.replacen(&field, &colored, 1)).collect()
+}
+
+/// The last lines written, kept when env `TMP_LOG_RING` is set.
+static RING: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+fn lock_ring() -> std::sync::MutexGuard<'static, VecDeque<String>> {
+    RING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Keep the physical lines of `line` in the ring, dropping the oldest
+/// beyond `TMP_LOG_RING` of them.
+fn remember(line: &str) {
+    let size = match std::env::var("TMP_LOG_RING").ok().and_then(|x| x.trim().parse().ok()) {
+        Some(n) if n > 0 => n,
+        _ => return,
+    };
+    let mut ring = lock_ring();
+    for part in line.lines() {
+        ring.push_back(part.to_string());
+    }
+    while ring.len() > size {
+        ring.pop_front();
+    }
+}
+
+/// The most recent lines, oldest first, when env `TMP_LOG_RING=N` keeps
+/// the last `N` of them in memory. Lines still go to the file as usual.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-ring-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// std::env::set_var("TMP_LOG_RING", "3");
+/// for i in 1..=5 {
+///     tlog::tlog!("line {}", i);
+/// }
+/// let recent = tlog::recent();
+/// assert_eq!(recent.len(), 3);
+/// assert!(recent[0].ends_with("] line 3"));
+/// assert!(recent[2].ends_with("] line 5"));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+pub fn recent() -> Vec<String> {
+    lock_ring().iter().cloned().collect()
+}
+
+/// Send a finished line on, unless the thread is in a `tlog_batch!`
+/// block, which keeps it for later.
+fn write_out(target: Option<&str>, data: &[u8]) -> io::Result<()> {
+    if batch::push(target, data) {
+        return Ok(());
+    }
+    send(target, data)
+}
+
+/// Send finished lines to the custom writer if one is set, else to the
+/// file routed for `target` or the log file.
+///
+/// The whole line goes out under the lock of the writer, the buffer or
+/// the cached file, so lines of different threads never interleave; all
+/// the formatting happens before, outside of it.
+fn send(target: Option<&str>, data: &[u8]) -> io::Result<()> {
+    guarded(|| send_now(target, data))
+}
+
+fn send_now(target: Option<&str>, data: &[u8]) -> io::Result<()> {
+    if let Some(ref mut w) = *lock_writer() {
+        return attempt(None, || w.write_all(data));
+    }
+    let path = target.and_then(routed).map(checked_path).unwrap_or_else(log_file);
+    if buffer::enabled() {
+        return buffer::push(path, data);
+    }
+    write_to_file(&path, data)
+}
+
+/// How long lines are dropped without trying after a write fails.
+const COOLDOWN: Duration = Duration::from_secs(1);
+
+/// Lines lost to write errors, see `write_failures`.
+static FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// The sinks whose last write failed, a file path or `None` for the
+/// `set_writer` one, with when to try them again.
+static RETRY_AT: Mutex<Vec<(Option<String>, Instant)>> = Mutex::new(Vec::new());
+
+fn lock_retry_at() -> std::sync::MutexGuard<'static, Vec<(Option<String>, Instant)>> {
+    RETRY_AT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Run `write` for `sink`, a file path or `None` for the `set_writer`
+/// one, unless it failed less than `COOLDOWN` ago: then the line is
+/// dropped, counted, and an error says so.
+///
+/// The first failure of an outage and the recovery are reported on
+/// stderr, once each.
+fn attempt(sink: Option<&str>, write: impl FnOnce() -> io::Result<()>) -> io::Result<()> {
+    let name = || sink.unwrap_or("the writer").to_string();
+    if let Some(&(_, at)) = lock_retry_at().iter().find(|r| r.0.as_deref() == sink) {
+        if Instant::now() < at {
+            FAILURES.fetch_add(1, Ordering::Relaxed);
+            return Err(io::Error::other("tlog: dropped during write cooldown"));
+        }
+    }
+    let result = write();
+    let mut retry_at = lock_retry_at();
+    let failing = retry_at.iter().position(|r| r.0.as_deref() == sink);
+    match (&result, failing) {
+        (Ok(()), Some(i)) => {
+            retry_at.remove(i);
+            eprintln!("tlog: writing to {} again, {} lines lost so far", name(), write_failures());
+        }
+        (Ok(()), None) => {}
+        (Err(e), failing) => {
+            FAILURES.fetch_add(1, Ordering::Relaxed);
+            let at = Instant::now() + COOLDOWN;
+            match failing {
+                Some(i) => retry_at[i].1 = at,
+                None => {
+                    eprintln!("tlog: writing to {} failed: {}, dropping its lines for {:?} at a time", name(), e, COOLDOWN);
+                    retry_at.push((sink.map(str::to_string), at));
+                }
+            }
+        }
+    }
+    result
+}
+
+/// The number of lines lost because writing them failed, or because
+/// they came during the cooldown after a failure.
+///
+/// When the disk is full, or the writer set with `set_writer` keeps
+/// erroring, tlog does not retry every line: after a failed write to a
+/// file or writer, the lines for that one are dropped for a second
+/// before trying again, and it is only reported on stderr once per
+/// outage. Other files are not affected. Both the lines whose write
+/// failed and the ones dropped without trying are counted here; the
+/// latter come back from `log_line` as a `dropped during write cooldown`
+/// error.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// use std::io::{self, Write};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+///
+/// struct Full(Arc<AtomicUsize>);
+///
+/// impl Write for Full {
+///     fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///         Err(io::Error::other("no space left on device"))
+///     }
+///     fn flush(&mut self) -> io::Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// let attempts = Arc::new(AtomicUsize::new(0));
+/// tlog::set_writer(Box::new(Full(attempts.clone())));
+/// for i in 0..1000 {
+///     tlog::tlog!("line {}", i);
+/// }
+/// let skipped = tlog::log_line("one more").unwrap_err();
+/// assert!(skipped.to_string().contains("cooldown"));
+/// tlog::clear_writer();
+///
+/// assert_eq!(tlog::write_failures(), 1001);
+/// assert!(attempts.load(Ordering::Relaxed) < 10);
+///
+/// // a broken route doesn't hold up the log file
+/// # let dir = std::env::temp_dir().join(format!("tlog-failures-{}", std::process::id()));
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// # std::fs::write(dir.join("file"), "").unwrap();
+/// # std::env::set_var("TMP_LOG_FILE", dir.join("t.log"));
+/// tlog::route("broken", dir.join("file/broken.log"));
+/// tlog::tlog_target!("broken", "lost");
+/// assert_eq!(tlog::write_failures(), 1002);
+/// assert!(tlog::log_line("kept").is_ok());
+/// # assert!(std::fs::read_to_string(dir.join("t.log")).unwrap().ends_with("] kept\n"));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// # }
+/// ```
+pub fn write_failures() -> u64 {
+    FAILURES.load(Ordering::Relaxed)
+}
+
+/// Calls `flush()` when dropped, see `init` and `flush_guard`.
+#[must_use = "the logs are flushed when the guard is dropped"]
+#[derive(Debug)]
+pub struct TlogGuard {
+    /// Also close the cached log file.
+    close: bool,
+}
+
+impl Drop for TlogGuard {
+    fn drop(&mut self) {
+        let _ = flush();
+        if self.close {
+            *lock_log_file() = None;
+        }
+    }
+}
+
+/// Set up logging for a program and return a guard to hold in `main`:
+/// when it is dropped, the buffered lines are written out and the log
+/// file is closed. Lines logged after that still work, reopening it.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-init-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// std::env::set_var("TMP_LOG_BUFFERED", "1");
+/// let guard = tlog::init();
+/// for i in 0..1000 {
+///     tlog::tlog!("line {}", i);
+/// }
+/// drop(guard);
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # assert_eq!(content.lines().count(), 1000);
+/// # assert!(content.ends_with("] line 999\n"));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+pub fn init() -> TlogGuard {
+    TlogGuard { close: true }
+}
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct DateTime {
-    odt: OffsetDateTime,
+/// A guard that flushes the buffered lines when it goes out of scope.
+/// Keep it alive in `main` with `TMP_LOG_BUFFERED=1`, so the lines of a
+/// program that exits right away are not lost with the background
+/// thread.
+pub fn flush_guard() -> TlogGuard {
+    TlogGuard { close: false }
+}
+
+/// Write one line saying how logging is set up for this run: the file,
+/// the format, level and other settings from the env, and the cargo
+/// features tlog was built with:
+///
+/// ```text
+/// [2022-09-05 11:10:31.763][15235] tlog 0.1.3: file=/tmp/t.log format=text level=all ... features=enabled
+/// ```
+///
+/// Only the first call of the process writes it.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-startup-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// std::env::set_var("TMP_LOG_FORMAT", "json");
+/// tlog::log_startup_info();
+/// tlog::log_startup_info();
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # assert_eq!(content.lines().count(), 1);
+/// # assert!(content.contains(&format!(" file={} ", path.display())));
+/// # assert!(content.contains(" format=json "));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+pub fn log_startup_info() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        let config = Config::from_env();
+        let on = |x: bool| if x { "on" } else { "off" };
+        let format = match config.format {
+            Format::Text => "text",
+            Format::Json => "json",
+        };
+        let precision = match config.precision {
+            Precision::Millis => "ms",
+            Precision::Micros => "us",
+            Precision::Nanos => "ns",
+        };
+        let clock = match config.clock {
+            logger::Clock::Wall => "wall",
+            logger::Clock::Mono => "mono",
+        };
+        let features = [
+            ("binary", cfg!(feature = "binary")),
+            ("enabled", cfg!(feature = "enabled")),
+            ("gzip", cfg!(feature = "gzip")),
+            ("log", cfg!(feature = "log")),
+            ("regex", cfg!(feature = "regex")),
+            ("serde", cfg!(feature = "serde")),
+            ("test-util", cfg!(feature = "test-util")),
+            ("tokio", cfg!(feature = "tokio")),
+            ("tracing", cfg!(feature = "tracing")),
+        ];
+        let features: Vec<&str> = features.iter().filter(|f| f.1).map(|f| f.0).collect();
+        let msg = format!(
+            "tlog {}: file={} format={} level={} precision={} clock={} tz={} host={} seq={} thread={} loc={} module={} buffered={} features={}",
+            env!("CARGO_PKG_VERSION"),
+            log_file(),
+            format,
+            config.max_level.map_or("all", |l| l.as_str()),
+            precision,
+            clock,
+            on(config.tz),
+            on(config.host),
+            on(config.seq),
+            on(config.thread),
+            on(config.loc),
+            on(config.module),
+            on(buffer::enabled()),
+            if features.is_empty() { "none".to_string() } else { features.join(",") },
+        );
+        let _ = write_line(None, None, &msg);
+    });
 }
 
-impl DateTime {
-    pub fn now() -> Self {
-        let odt: OffsetDateTime;
-        match OffsetDateTime::now_local() {
-            Ok(dt) => {
-                odt = dt;
+/// Log panics to the log file as `ERROR` lines, with their location and,
+/// when `RUST_BACKTRACE` asks for one, a backtrace. The previous hook
+/// still runs afterwards. Calling this more than once has no effect.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-panic-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// tlog::install_panic_hook();
+/// tlog::install_panic_hook();
+///
+/// let result = std::thread::Builder::new()
+///     .name("doomed".to_string())
+///     .spawn(|| panic!("boom"))
+///     .unwrap()
+///     .join();
+/// assert!(result.is_err());
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # assert_eq!(content.matches("panicked").count(), 1);
+/// # assert!(content.contains(&format!("[ERROR] thread 'doomed' panicked at {}:", file!())), "{}", content);
+/// # assert!(content.contains(": boom"));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+///
+/// A panic in tlog's own writing, say in a `set_writer` sink, is left to
+/// the previous hook:
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// use std::io::{self, Write};
+///
+/// struct Broken;
+///
+/// impl Write for Broken {
+///     fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+///         panic!("broken sink")
+///     }
+///     fn flush(&mut self) -> io::Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// tlog::install_panic_hook();
+/// tlog::set_writer(Box::new(Broken));
+/// assert!(std::panic::catch_unwind(|| tlog::tlog!("hi")).is_err());
+/// tlog::clear_writer();
+/// # }
+/// ```
+pub fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if writing() {
+                // e.g. a `set_writer` sink that panicked
+                return previous(info);
             }
-            Err(_) => {
-                odt = OffsetDateTime::now_utc();
+            let payload = info.payload();
+            let msg = match payload.downcast_ref::<&str>() {
+                Some(s) => s,
+                None => match payload.downcast_ref::<String>() {
+                    Some(s) => s.as_str(),
+                    None => "Box<dyn Any>",
+                },
+            };
+            let location = match info.location() {
+                Some(l) => format!("{}:{}:{}", l.file(), l.line(), l.column()),
+                None => "<unknown>".to_string(),
+            };
+            let mut line = format!("thread '{}' panicked at {}: {}", thread_name(), location, msg);
+            let backtrace = std::backtrace::Backtrace::capture();
+            if backtrace.status() == std::backtrace::BacktraceStatus::Captured {
+                line.push_str(&format!("\n{}", backtrace));
             }
+            let _ = write_line(Some(Level::Error), None, &line);
+            previous(info);
+        }));
+    });
+}
+
+/// When the process started logging: the first call of this, which the
+/// first log line makes.
+fn start() -> Instant {
+    static START: OnceLock<Instant> = OnceLock::new();
+    *START.get_or_init(Instant::now)
+}
+
+/// Milliseconds since `start()`, from the monotonic clock.
+fn monotonic_ms() -> u64 {
+    start().elapsed().as_millis() as u64
+}
+
+/// Whether a `tlog_every!` site whose last line was written at `last`
+/// may log again. `last` holds `monotonic_ms() + 1`, so 0 means never.
+#[doc(hidden)]
+pub fn __every(last: &AtomicU64, interval_ms: u64) -> bool {
+    let now = monotonic_ms() + 1;
+    let prev = last.load(Ordering::Relaxed);
+    // another thread may have stored a later `now` since the clock was read
+    if prev != 0 && now.saturating_sub(prev) < interval_ms {
+        return false;
+    }
+    // only one of several racing threads wins the slot
+    last.compare_exchange(prev, now, Ordering::Relaxed, Ordering::Relaxed).is_ok()
+}
+
+/// Logs how long it lived when dropped, see `tlog_timer!`.
+#[must_use = "the time is logged when the timer is dropped"]
+#[derive(Debug)]
+pub struct TlogTimer {
+    label: String,
+    /// `None` for the do-nothing timer of `tlog_timer!` when the
+    /// `enabled` feature is off.
+    start: Option<Instant>,
+    site: Option<Site>,
+}
+
+impl TlogTimer {
+    pub fn new(label: impl Into<String>) -> Self {
+        TlogTimer { label: label.into(), start: Some(Instant::now()), site: None }
+    }
+
+    #[doc(hidden)]
+    pub fn __at(label: String, site: Site) -> Self {
+        TlogTimer { label, start: Some(Instant::now()), site: Some(site) }
+    }
+
+    #[doc(hidden)]
+    pub fn __off() -> Self {
+        TlogTimer { label: String::new(), start: None, site: None }
+    }
+
+    /// Time since the timer was created, always zero for a `tlog_timer!`
+    /// without the `enabled` feature.
+    pub fn elapsed(&self) -> Duration {
+        self.start.map_or(Duration::ZERO, |start| start.elapsed())
+    }
+}
+
+impl Drop for TlogTimer {
+    fn drop(&mut self) {
+        if self.start.is_none() {
+            return;
         }
-        DateTime { odt }
+        let ms = self.elapsed().as_secs_f64() * 1000.0;
+        let _ = write_line(None, self.site, &format!("{}: {:.1}ms", self.label, ms));
     }
 }
 
-impl fmt::Display for DateTime {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
-            self.odt.year(),
-            self.odt.month() as u8,
-            self.odt.day(),
-            self.odt.hour(),
-            self.odt.minute(),
-            self.odt.second(),
-            self.odt.millisecond(),
-        )
+/// The body of `tlog_hex!`: every line of the dump gets a header.
+#[doc(hidden)]
+pub fn __hex(site: Site, bytes: &[u8]) {
+    let _ = write_line_with(None, Some(site), &hex::dump(bytes), &[], Some(Multiline::Prefix), None);
+}
+
+/// Log `msg` with the location of `site` in front, as `TMP_LOG_LOC=1`
+/// would, whatever it says: the body of `tlogd!` and `tlog_type!`.
+#[doc(hidden)]
+pub fn __log_at(level: Option<Level>, site: Site, msg: &str) {
+    if __ENABLED {
+        let mut config = Config::from_env();
+        config.loc = true;
+        let _ = write_line_config(config, level, Some(site), msg, &[], None);
     }
 }
 
-pub fn getpid() -> i32 {
-    unsafe { libc::getpid() }
+/// The body of `tlog_assert!` and `tlog_assert_panic!` once `cond` turned
+/// out false: log it, and return the message to panic with.
+#[doc(hidden)]
+pub fn __assert_failed(site: Site, cond: &str, msg: Option<fmt::Arguments<'_>>) -> String {
+    let text = match msg {
+        Some(msg) => format!("{}: {}", cond, msg),
+        None => cond.to_string(),
+    };
+    __log_at(Some(Level::Error), site, &format!("ASSERT FAILED: {}", text));
+    // the program may be about to panic; make sure the line is out
+    let _ = flush();
+    text
 }
 
-#[macro_export]
-macro_rules! tlog {
-    ($fmt:expr) => (
-        use std::io::Write as _;
+/// Where a line for `target` goes: the file, or `None` for the
+/// `set_writer` sink.
+fn sink_of(target: Option<&str>) -> Option<String> {
+    if lock_writer().is_some() {
+        return None;
+    }
+    Some(target.and_then(routed).map(checked_path).unwrap_or_else(log_file))
+}
+
+type RawLine = (std::thread::ThreadId, Option<String>);
+
+/// The line the last `tlog_raw!` piece left open: the thread that wrote
+/// it, and its sink.
+static RAW_OPEN: Mutex<Option<RawLine>> = Mutex::new(None);
+
+fn lock_raw_open() -> std::sync::MutexGuard<'static, Option<RawLine>> {
+    RAW_OPEN.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A whole line is about to go to the sink of `target`: a raw line open
+/// there gets broken up, so its next piece needs a header again.
+fn close_raw(target: Option<&str>) {
+    if lock_raw_open().is_none() {
+        return;
+    }
+    let sink = sink_of(target);
+    let mut open = lock_raw_open();
+    if open.as_ref().is_some_and(|o| o.1 == sink) {
+        *open = None;
+    }
+}
 
-        let msg = $fmt;
-        let default_log_file = String::from("/tmp/t.log");
-        let log_file = if let Ok(x) = std::env::var("TMP_LOG_FILE") {
-            if x.is_empty() { default_log_file } else { x.clone() }
+/// The body of `tlog_raw!`.
+#[doc(hidden)]
+pub fn __raw(site: Site, msg: &str) {
+    if !__ENABLED || filter::excluded(msg) {
+        return;
+    }
+    let config = Config::from_env();
+    let raw = config.format == Format::Text;
+    if !raw {
+        close_raw(None);
+    }
+    let line = (std::thread::current().id(), sink_of(None));
+    let piece = {
+        let mut open = lock_raw_open();
+        let piece = if raw && open.as_ref() == Some(&line) {
+            msg.to_string()
         } else {
-            default_log_file
+            let mut piece = config.render(None, Some(site), msg, &SEQ);
+            if raw && !msg.ends_with('\n') {
+                piece.pop();
+            }
+            piece
         };
+        if raw && !msg.ends_with('\n') {
+            *open = Some(line);
+        } else if open.as_ref().is_some_and(|o| o.1 == line.1) {
+            *open = None;
+        }
+        piece
+    };
+    if env_flag("TMP_LOG_STDERR") {
+        let _ = io::stderr().write_all(piece.as_bytes());
+    }
+    let _ = write_out(None, piece.as_bytes());
+}
+
+/// The body of `tlog_bin!`.
+#[cfg(feature = "binary")]
+#[doc(hidden)]
+pub fn __bin(msg: &str) {
+    if __ENABLED && !filter::excluded(msg) {
+        let _ = binary::write(msg);
+    }
+}
+
+/// The body of `tlog_pretty!`: every line gets a header.
+#[doc(hidden)]
+pub fn __pretty(site: Site, msg: &str) {
+    let _ = write_line_with(None, Some(site), msg, &[], Some(Multiline::Prefix), None);
+}
+
+/// The body of `tlog_since!`.
+#[doc(hidden)]
+pub fn __since(site: Site, start: &DateTime, msg: &str) {
+    let ms = DateTime::now().elapsed_since(start).as_seconds_f64() * 1000.0;
+    let _ = write_line(None, Some(site), &format!("+{:.1}ms {}", ms, msg));
+}
+
+/// The body of `tlog_target!`.
+#[doc(hidden)]
+pub fn __log_target(target: &str, site: Site, args: fmt::Arguments<'_>) {
+    let _ = with_buffer(&MSG, |msg| {
+        let _ = fmt::Write::write_fmt(msg, args);
+        write_line_with(None, Some(site), msg, &[], None, Some(target))
+    });
+}
+
+/// The body of `tlog_kv!`.
+#[doc(hidden)]
+pub fn __kv(site: Site, event: &str, fields: &[(&str, KvValue)]) {
+    let _ = write_line_with(None, Some(site), event, fields, None, None);
+}
+
+/// The shared body of all the `tlog*!` macros.
+#[doc(hidden)]
+pub fn __log(level: Option<Level>, site: Site, args: fmt::Arguments<'_>) {
+    // a debug logger must never take the program down with it
+    let _ = match args.as_str() {
+        Some(msg) => write_line(level, Some(site), msg),
+        None => with_buffer(&MSG, |msg| {
+            let _ = fmt::Write::write_fmt(msg, args);
+            write_line(level, Some(site), msg)
+        }),
+    };
+}
 
-        let mut cfile;
-        match std::fs::OpenOptions::new().append(true).create(true).open(&log_file) {
-            Ok(x) => cfile = x,
-            Err(_) => panic!("tlog: open file error"),
+/// Write a line to the log file, `format!` style.
+///
+/// Errors opening or writing the file are ignored, so `tlog!` can be
+/// dropped into any function without changing its control flow:
+///
+/// ```
+/// use tlog::tlog;
+///
+/// fn answer() -> i32 {
+///     tlog!("computing the answer");
+///     42
+/// }
+///
+/// # let file = std::env::temp_dir().join(format!("tlog-unwritable-{}", std::process::id()));
+/// # std::fs::write(&file, "").unwrap();
+/// // under a regular file, the log file can't be created
+/// std::env::set_var("TMP_LOG_FILE", file.join("t.log"));
+/// assert_eq!(answer(), 42);
+/// # assert_eq!(std::fs::read_to_string(&file).unwrap(), "");
+/// # std::fs::remove_file(&file).unwrap();
+/// ```
+///
+/// Nothing is imported into the caller's scope, so it plays well with a
+/// `Write` trait the caller already uses:
+///
+/// ```
+/// #![deny(warnings)]
+/// use std::io::Write;
+/// use tlog::tlog;
+///
+/// # let path = std::env::temp_dir().join(format!("tlog-hygiene-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// let mut out = Vec::new();
+/// tlog!("writing {} bytes", 2);
+/// out.write_all(b"ok").unwrap();
+/// # let _ = std::fs::remove_file(&path);
+/// ```
+#[macro_export]
+macro_rules! tlog {
+    ($($arg:tt)+) => (
+        $crate::__tlog!(None, $($arg)+)
+    );
+}
+
+#[macro_export]
+macro_rules! tlog_error {
+    ($($arg:tt)+) => (
+        $crate::__tlog!(Some($crate::Level::Error), $($arg)+)
+    );
+}
+
+#[macro_export]
+macro_rules! tlog_warn {
+    ($($arg:tt)+) => (
+        $crate::__tlog!(Some($crate::Level::Warn), $($arg)+)
+    );
+}
+
+#[macro_export]
+macro_rules! tlog_info {
+    ($($arg:tt)+) => (
+        $crate::__tlog!(Some($crate::Level::Info), $($arg)+)
+    );
+}
+
+#[macro_export]
+macro_rules! tlog_debug {
+    ($($arg:tt)+) => (
+        $crate::__tlog!(Some($crate::Level::Debug), $($arg)+)
+    );
+}
+
+/// Log an expression and its `Debug` value, then hand the value back,
+/// like `std::dbg!` but into the log file:
+///
+/// ```text
+/// [2022-09-05 11:10:31.763][15235] src/main.rs:10: compute() = 35
+/// ```
+///
+/// The expression is evaluated exactly once and moved through, so it
+/// works in any expression position:
+///
+/// ```
+/// # let path = std::env::temp_dir().join(format!("tlog-tlogd-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use tlog::tlogd;
+///
+/// let v = tlogd!(vec![1, 2, 3]);
+/// assert_eq!(v, [1, 2, 3]);
+/// let n = tlogd!(5 * 7) + 1;
+/// assert_eq!(n, 36);
+/// # let _ = std::fs::remove_file(&path);
+/// ```
+#[macro_export]
+macro_rules! tlogd {
+    ($val:expr $(,)?) => (
+        match $val {
+            tmp => {
+                if $crate::__ENABLED {
+                    $crate::__log_at(::std::option::Option::None, $crate::__site!(),
+                        &::std::format!("{} = {:?}", ::std::stringify!($val), &tmp));
+                }
+                tmp
+            }
+        }
+    );
+}
+
+/// A soft `assert!`: when `cond` is false, log the condition and the
+/// optional `format!` style message at `Level::Error`, then carry on.
+///
+/// ```text
+/// [2022-09-05 11:10:31.763][15235][ERROR] src/main.rs:10: ASSERT FAILED: n < 10: n is 12
+/// ```
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-assert-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use tlog::tlog_assert;
+///
+/// let result = std::panic::catch_unwind(|| {
+///     let n = 12;
+///     tlog_assert!(n > 0);
+///     tlog_assert!(n < 10, "n is {}", n);
+/// });
+/// assert!(result.is_ok());
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # assert_eq!(content.lines().count(), 1);
+/// # assert!(content.contains(&format!("[ERROR] {}:", file!())));
+/// # assert!(content.ends_with(": ASSERT FAILED: n < 10: n is 12\n"));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tlog_assert {
+    ($cond:expr $(,)?) => ({
+        if !$cond {
+            $crate::__assert_failed($crate::__site!(), ::std::stringify!($cond), ::std::option::Option::None);
+        }
+    });
+
+    ($cond:expr, $($arg:tt)+) => ({
+        if !$cond {
+            $crate::__assert_failed(
+                $crate::__site!(),
+                ::std::stringify!($cond),
+                ::std::option::Option::Some(::std::format_args!($($arg)+)),
+            );
+        }
+    });
+}
+
+/// Like `tlog_assert!`, but panics after logging, like `assert!` does.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-assert-panic-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use tlog::tlog_assert_panic;
+///
+/// let result = std::panic::catch_unwind(|| {
+///     let n = 12;
+///     tlog_assert_panic!(n < 10, "n is {}", n);
+/// });
+/// assert!(result.is_err());
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # assert!(content.ends_with(": ASSERT FAILED: n < 10: n is 12\n"));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tlog_assert_panic {
+    ($cond:expr $(,)?) => ({
+        if !$cond {
+            let msg = $crate::__assert_failed($crate::__site!(), ::std::stringify!($cond), ::std::option::Option::None);
+            ::std::panic!("assertion failed: {}", msg);
+        }
+    });
+
+    ($cond:expr, $($arg:tt)+) => ({
+        if !$cond {
+            let msg = $crate::__assert_failed(
+                $crate::__site!(),
+                ::std::stringify!($cond),
+                ::std::option::Option::Some(::std::format_args!($($arg)+)),
+            );
+            ::std::panic!("assertion failed: {}", msg);
+        }
+    });
+}
+
+/// Like `tlogd!`, with the type of the value too:
+///
+/// ```text
+/// [2022-09-05 11:10:31.763][15235] src/main.rs:10: x: i32 = 42
+/// ```
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-type-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use tlog::tlog_type;
+///
+/// let name = tlog_type!(String::from("tlog"));
+/// let bytes = tlog_type!(vec![1u8, 2, 3]);
+/// assert_eq!(name, "tlog");
+/// assert_eq!(bytes, [1, 2, 3]);
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # let lines: Vec<_> = content.lines().collect();
+/// # assert!(lines[0].ends_with(r#": String::from("tlog"): String = "tlog""#));
+/// # assert!(lines[1].ends_with(": vec![1u8, 2, 3]: Vec<u8> = [1, 2, 3]"));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tlog_type {
+    ($val:expr $(,)?) => (
+        match $val {
+            tmp => {
+                if $crate::__ENABLED {
+                    $crate::__log_at(::std::option::Option::None, $crate::__site!(),
+                        &::std::format!("{}: {} = {:?}", ::std::stringify!($val),
+                            $crate::type_name_short(&tmp), &tmp));
+                }
+                tmp
+            }
         }
-        let pid = tlog::getpid();
-        let now = tlog::DateTime::now();
-        let msg = format!("[{}][{}] {}", now, pid, msg);
-        let msg = if msg.ends_with('\n') { msg } else { format!("{}\n", msg) };
-        match cfile.write_all(msg.as_bytes()) {
-            Ok(_) => {}
-            Err(_) => panic!("tlog: write_all error")
+    );
+}
+
+/// Start a `TlogTimer` that logs `label: 12.3ms` when it goes out of
+/// scope. The label takes `format!` arguments too.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-timer-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use tlog::tlog_timer;
+///
+/// {
+///     let _t = tlog_timer!("db query");
+///     {
+///         let _inner = tlog_timer!("row {}", 1);
+///         std::thread::sleep(std::time::Duration::from_millis(20));
+///     }
+/// }
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # let ms = |label: &str| -> f64 {
+/// #     let line = content.lines().find(|l| l.contains(label)).unwrap();
+/// #     line.rsplit(": ").next().unwrap().trim_end_matches("ms").parse().unwrap()
+/// # };
+/// # assert!(ms("] row 1: ") >= 20.0);
+/// # assert!(ms("] db query: ") >= ms("] row 1: "));
+/// # assert!(content.lines().next().unwrap().contains("] row 1: "));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tlog_timer {
+    ($label:expr) => (
+        if $crate::__ENABLED {
+            $crate::TlogTimer::__at(::std::format!("{}", $label), $crate::__site!())
+        } else {
+            $crate::TlogTimer::__off()
         }
     );
 
     ($fmt:expr, $($arg:tt)*) => (
-        let msg = format!($fmt, $($arg)*);
-        tlog!(&msg);
+        if $crate::__ENABLED {
+            $crate::TlogTimer::__at(::std::format!($fmt, $($arg)*), $crate::__site!())
+        } else {
+            $crate::TlogTimer::__off()
+        }
+    );
+}
+
+/// Like `tlog!`, with the time since the `DateTime` `start` in front of
+/// the message:
+///
+/// ```text
+/// [2022-09-05 11:10:31.763][15235] +20.1ms request sent
+/// ```
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-since-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use tlog::{tlog_since, DateTime};
+///
+/// let start = DateTime::now();
+/// std::thread::sleep(std::time::Duration::from_millis(20));
+/// tlog_since!(start, "request {}", "sent");
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # let ms: f64 = content.split("] +").nth(1).unwrap().split("ms ").next().unwrap().parse().unwrap();
+/// # assert!(ms >= 20.0);
+/// # assert!(content.ends_with("ms request sent\n"));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tlog_since {
+    ($start:expr, $fmt:expr) => ({
+        if $crate::__ENABLED {
+            $crate::__since($crate::__site!(), &$start, &::std::format!("{}", $fmt));
+        }
+    });
+
+    ($start:expr, $fmt:expr, $($arg:tt)*) => ({
+        if $crate::__ENABLED {
+            $crate::__since($crate::__site!(), &$start, &::std::format!($fmt, $($arg)*));
+        }
+    });
+}
+
+/// Like `tlog!`, but written to the file registered for `target` with
+/// `route`, see there.
+#[macro_export]
+macro_rules! tlog_target {
+    ($target:expr, $fmt:expr) => ({
+        if $crate::__ENABLED {
+            $crate::__log_target(&$target, $crate::__site!(), ::std::format_args!("{}", $fmt));
+        }
+    });
+
+    ($target:expr, $fmt:expr, $($arg:tt)*) => ({
+        if $crate::__ENABLED {
+            $crate::__log_target(&$target, $crate::__site!(), ::std::format_args!($fmt, $($arg)*));
+        }
+    });
+}
+
+/// Like `tlog!`, but appends a binary `LogRecord` to `bin_file_path()`
+/// instead of a text line, for `read_bin` to read back. Needs the
+/// `binary` feature.
+#[cfg(feature = "binary")]
+#[macro_export]
+macro_rules! tlog_bin {
+    ($fmt:expr) => ({
+        if $crate::__ENABLED {
+            $crate::__bin(&::std::format!("{}", $fmt));
+        }
+    });
+
+    ($fmt:expr, $($arg:tt)*) => ({
+        if $crate::__ENABLED {
+            $crate::__bin(&::std::format!($fmt, $($arg)*));
+        }
+    });
+}
+
+/// Like `tlog!`, but without adding a newline, to build a line over
+/// several calls. Only the first piece of a line gets the header; the
+/// caller writes the `\n` that ends it. A line logged to the same file
+/// in between, or a piece from another thread, ends up in the middle of
+/// it, and the next piece starts over with a header.
+///
+/// With `TMP_LOG_FORMAT=json` this is the same as `tlog!`.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-raw-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use tlog::tlog_raw;
+///
+/// tlog_raw!("loading...");
+/// tlog_raw!(" done in {}ms\n", 12);
+///
+/// tlog_raw!("saving...");
+/// tlog::tlog!("disk is slow");
+/// tlog_raw!(" done\n");
+///
+/// std::thread::spawn(|| tlog_raw!("other thread")).join().unwrap();
+/// tlog_raw!("\n");
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # let lines: Vec<_> = content.lines().collect();
+/// # assert_eq!(lines.len(), 4, "{}", content);
+/// # assert!(lines[0].ends_with("] loading... done in 12ms"));
+/// # assert!(lines[1].contains("] saving...[") && lines[1].ends_with("] disk is slow"));
+/// # assert!(lines[2].starts_with('[') && lines[2].ends_with("]  done"));
+/// # assert!(lines[3].starts_with('[') && lines[3].contains("] other thread["));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tlog_raw {
+    ($fmt:expr) => ({
+        if $crate::__ENABLED {
+            $crate::__raw($crate::__site!(), &::std::format!("{}", $fmt));
+        }
+    });
+
+    ($fmt:expr, $($arg:tt)*) => ({
+        if $crate::__ENABLED {
+            $crate::__raw($crate::__site!(), &::std::format!($fmt, $($arg)*));
+        }
+    });
+}
+
+/// Run a block, collecting the lines logged in it by this thread and
+/// writing them with a single write when it ends, rather than one each.
+/// Nested blocks are written with the outermost one. Unlike
+/// `TMP_LOG_BUFFERED`, nothing is left waiting once the block is done.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// use std::io::{self, Write};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+/// use tlog::{tlog, tlog_batch};
+///
+/// #[derive(Clone, Default)]
+/// struct Writes(Arc<AtomicUsize>);
+///
+/// impl Write for Writes {
+///     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///         Ok(buf.len())
+///     }
+///     fn flush(&mut self) -> io::Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// let writes = Writes::default();
+/// tlog::set_writer(Box::new(writes.clone()));
+/// let sum = tlog_batch! {
+///     let mut sum = 0;
+///     for i in 0..10 {
+///         tlog!("adding {}", i);
+///         sum += i;
+///     }
+///     sum
+/// };
+/// assert_eq!(sum, 45);
+/// assert_eq!(writes.0.load(Ordering::Relaxed), 1);
+/// # tlog::clear_writer();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tlog_batch {
+    ($($body:tt)*) => ({
+        let _batch = $crate::__batch();
+        { $($body)* }
+    });
+}
+
+/// Like `tlog!` when `cond` is true. When it is false the format
+/// arguments are not even evaluated.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-if-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use tlog::tlog_if;
+///
+/// let mut calls = 0;
+/// let mut expensive = || { calls += 1; calls };
+/// tlog_if!(false, "skipped {}", expensive());
+/// tlog_if!(1 + 1 == 2, "written {}", expensive());
+/// assert_eq!(calls, 1);
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # assert!(content.ends_with("] written 1\n") && !content.contains("skipped"));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tlog_if {
+    ($cond:expr, $($arg:tt)+) => ({
+        if $cond {
+            $crate::__tlog!(None, $($arg)+);
+        }
+    });
+}
+
+/// Like `tlog!`, but each call site only logs the first time it is
+/// reached.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-once-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use tlog::tlog_once;
+///
+/// for i in 0..100 {
+///     tlog_once!("entered the loop at {}", i);
+/// }
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # assert_eq!(content.lines().count(), 1);
+/// # assert!(content.ends_with("] entered the loop at 0\n"));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tlog_once {
+    ($($arg:tt)+) => ({
+        static DONE: ::std::sync::atomic::AtomicBool = ::std::sync::atomic::AtomicBool::new(false);
+        if !DONE.swap(true, ::std::sync::atomic::Ordering::Relaxed) {
+            $crate::__tlog!(None, $($arg)+);
+        }
+    });
+}
+
+/// Like `tlog!`, but each call site only logs the first `n` times it is
+/// reached.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-first-n-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use tlog::tlog_first_n;
+///
+/// for i in 0..10 {
+///     tlog_first_n!(3, "iteration {}", i);
+/// }
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # assert_eq!(content.lines().count(), 3);
+/// # assert!(content.ends_with("] iteration 2\n"));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tlog_first_n {
+    ($n:expr, $($arg:tt)+) => ({
+        static COUNT: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
+        let n: usize = $n;
+        // the load keeps the counter from growing once it is done
+        if COUNT.load(::std::sync::atomic::Ordering::Relaxed) < n
+            && COUNT.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed) < n
+        {
+            $crate::__tlog!(None, $($arg)+);
+        }
+    });
+}
+
+/// Like `tlog!`, but each call site only logs one in every `n` times it
+/// is reached: the first, the `n+1`th and so on. The skipped calls are
+/// not formatted.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-sample-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use tlog::tlog_sample;
+///
+/// for i in 0..100 {
+///     tlog_sample!(10, "call {}", i);
+/// }
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # let calls: Vec<u32> = content.lines().map(|l| l.rsplit(' ').next().unwrap().parse().unwrap()).collect();
+/// # assert_eq!(calls, [0, 10, 20, 30, 40, 50, 60, 70, 80, 90]);
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tlog_sample {
+    ($n:expr, $($arg:tt)+) => ({
+        static COUNT: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
+        let n: usize = $n;
+        if n > 0 && COUNT.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed) % n == 0 {
+            $crate::__tlog!(None, $($arg)+);
+        }
+    });
+}
+
+/// Like `tlog!`, but each call site logs at most once every `ms`
+/// milliseconds; calls in between are skipped without formatting.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-every-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use std::time::{Duration, Instant};
+/// use tlog::tlog_every;
+///
+/// let start = Instant::now();
+/// let mut i = 0;
+/// while start.elapsed() < Duration::from_millis(200) {
+///     tlog_every!(50, "iteration {}", i);
+///     i += 1;
+/// }
+/// # let n = std::fs::read_to_string(&path).unwrap().lines().count();
+/// # assert!((1..=6).contains(&n), "{} lines", n);
+/// # std::fs::remove_file(&path).unwrap();
+///
+/// // the same site from many threads at once
+/// let threads: Vec<_> = (0..16)
+///     .map(|_| {
+///         std::thread::spawn(|| {
+///             let start = Instant::now();
+///             while start.elapsed() < Duration::from_millis(100) {
+///                 tlog_every!(60_000, "racing");
+///             }
+///         })
+///     })
+///     .collect();
+/// for t in threads {
+///     t.join().unwrap();
+/// }
+/// # let n = std::fs::read_to_string(&path).unwrap().lines().count();
+/// # assert_eq!(n, 1);
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tlog_every {
+    ($ms:expr, $($arg:tt)+) => ({
+        static LAST: ::std::sync::atomic::AtomicU64 = ::std::sync::atomic::AtomicU64::new(0);
+        if $crate::__every(&LAST, $ms) {
+            $crate::__tlog!(None, $($arg)+);
+        }
+    });
+}
+
+/// Log a hexdump of anything `AsRef<[u8]>`, sixteen bytes per line,
+/// each line with the usual header:
+///
+/// ```text
+/// [2022-09-05 11:10:31.763][15235] 00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 ff  |Hello, world!...|
+/// ```
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-hex-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use tlog::tlog_hex;
+///
+/// tlog_hex!(b"Hello, world!\n\x00\xff and more");
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # let lines: Vec<_> = content.lines().collect();
+/// # assert_eq!(lines.len(), 2);
+/// # assert!(lines[0].ends_with("] 00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 ff  |Hello, world!...|"));
+/// # assert!(lines[1].contains("] 00000010  20 61 6e 64 "));
+/// # assert!(lines[1].ends_with("| and more|"));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tlog_hex {
+    ($bytes:expr $(,)?) => ({
+        if $crate::__ENABLED {
+            $crate::__hex($crate::__site!(), ::std::convert::AsRef::<[u8]>::as_ref(&$bytes));
+        }
+    });
+}
+
+/// `set_version` to the `CARGO_PKG_VERSION` of the crate calling it.
+#[macro_export]
+macro_rules! tlog_set_version {
+    () => (
+        $crate::set_version(::std::env!("CARGO_PKG_VERSION"))
+    );
+}
+
+/// Log `expr = value` with the value pretty printed by `{:#?}`, each of
+/// its lines with the usual header:
+///
+/// ```text
+/// [2022-09-05 11:10:31.763][15235] pairs = [
+/// [2022-09-05 11:10:31.763][15235]     (
+/// [2022-09-05 11:10:31.763][15235]         1,
+/// [2022-09-05 11:10:31.763][15235]         "one",
+/// [2022-09-05 11:10:31.763][15235]     ),
+/// ...
+/// ```
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-pretty-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use tlog::tlog_pretty;
+///
+/// let pairs: Vec<(i32, &str)> = vec![(1, "one"), (2, "two")];
+/// tlog_pretty!(pairs);
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # let lines: Vec<_> = content.lines().collect();
+/// # assert_eq!(lines.len(), 10);
+/// # assert!(lines.iter().all(|l| l.starts_with('[')));
+/// # assert!(lines[0].ends_with("] pairs = ["));
+/// # assert!(lines[2].ends_with("]         1,"));
+/// # assert!(lines[3].ends_with("]         \"one\","));
+/// # assert!(lines[7].ends_with("]         \"two\","));
+/// # assert!(lines[9].ends_with("] ]"));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tlog_pretty {
+    ($val:expr $(,)?) => ({
+        if $crate::__ENABLED {
+            $crate::__pretty(
+                $crate::__site!(),
+                &::std::format!("{} = {:#?}", ::std::stringify!($val), &$val),
+            );
+        }
+    });
+}
+
+/// Log an event name followed by `key=value` fields, each value written
+/// with `Display`. Values with spaces in them are quoted:
+///
+/// ```text
+/// [2022-09-05 11:10:31.763][15235] login user=alice tries=3 agent="curl 7.1"
+/// ```
+///
+/// With `TMP_LOG_FORMAT=json` the fields become keys of the object next
+/// to `msg`. Values of number and `bool` types are written unquoted,
+/// everything else as a string, `"12345"` included. A field named like
+/// one of the line's own keys (`ts`, `pid`, `msg`, ...) gets a `field.`
+/// prefix.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-kv-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use tlog::tlog_kv;
+///
+/// let user = "alice";
+/// tlog_kv!("login", user = user, tries = 3, agent = "curl 7.1");
+///
+/// std::env::set_var("TMP_LOG_FORMAT", "json");
+/// tlog_kv!("login", user = user, tries = 3, agent = "curl 7.1");
+/// tlog_kv!("order", zip = "12345", gift = "true", paid = true, total = 9.5, pid = 7);
+/// # std::env::remove_var("TMP_LOG_FORMAT");
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # let lines: Vec<_> = content.lines().collect();
+/// # assert!(lines[0].ends_with(r#"] login user=alice tries=3 agent="curl 7.1""#), "{}", lines[0]);
+/// # assert!(lines[1].ends_with(r#","msg":"login","user":"alice","tries":3,"agent":"curl 7.1"}"#), "{}", lines[1]);
+/// # assert!(lines[2].ends_with(r#","msg":"order","zip":"12345","gift":"true","paid":true,"total":9.5,"field.pid":7}"#), "{}", lines[2]);
+/// # let v: serde_json::Value = serde_json::from_str(lines[2]).unwrap();
+/// # assert_eq!(v["pid"], std::process::id());
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tlog_kv {
+    ($event:expr $(, $key:ident = $value:expr)* $(,)?) => ({
+        if $crate::__ENABLED {
+            $crate::__kv(
+                $crate::__site!(),
+                &::std::string::ToString::to_string(&$event),
+                &[$((::std::stringify!($key), {
+                    #[allow(unused_imports)]
+                    use $crate::{KvDisplay as _, KvTyped as _};
+                    (&$crate::KvWrap(&$value)).__kv_value()
+                })),*],
+            );
+        }
+    });
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __site {
+    () => (
+        $crate::Site { file: ::std::file!(), line: ::std::line!(), module: ::std::module_path!() }
     );
 }
 
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __tlog {
+    ($level:expr, $fmt:expr) => ({
+        if $crate::__ENABLED {
+            $crate::__log($level, $crate::__site!(), ::std::format_args!("{}", $fmt));
+        }
+    });
+
+    ($level:expr, $fmt:expr, $($arg:tt)*) => ({
+        if $crate::__ENABLED {
+            $crate::__log($level, $crate::__site!(), ::std::format_args!($fmt, $($arg)*));
+        }
+    });
+}
+
 pub fn type_name<T>(_: &T) -> String {
-    format!("{}", std::any::type_name::<T>())
+    std::any::type_name::<T>().to_string()
+}
+
+/// Like `type_name`, without the module paths:
+///
+/// ```
+/// use tlog::{type_name, type_name_short};
+///
+/// let v: Vec<Option<i32>> = vec![];
+/// assert_eq!(type_name(&v), "alloc::vec::Vec<core::option::Option<i32>>");
+/// assert_eq!(type_name_short(&v), "Vec<Option<i32>>");
+/// assert_eq!(type_name_short(&"hi"), "&str");
+/// let m: std::collections::HashMap<String, &[u8]> = Default::default();
+/// assert_eq!(type_name_short(&m), "HashMap<String, &[u8]>");
+/// ```
+pub fn type_name_short<T>(_: &T) -> String {
+    strip_paths(std::any::type_name::<T>())
+}
+
+fn strip_paths(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    // where the path being copied starts in `out`
+    let mut start = 0;
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == ':' && chars.peek() == Some(&':') {
+            chars.next();
+            out.truncate(start);
+        } else if c.is_alphanumeric() || c == '_' {
+            out.push(c);
+        } else {
+            out.push(c);
+            start = out.len();
+        }
+    }
+    out
 }
@@ -0,0 +1,65 @@
+//! A `log` crate backend, with the `log` feature.
+
+use crate::{write_line, Level, Site};
+
+/// Writes the records of the `log` macros as `tlog` lines:
+///
+/// ```text
+/// [2022-09-05 11:10:31.763][15235][WARN ] my_crate::net: connection reset
+/// ```
+///
+/// Install it with `init_log_backend`. `TMP_LOG_LEVEL` applies as for
+/// the `tlog_*!` macros; `trace` records are written as `DEBUG`.
+#[derive(Debug, Default)]
+pub struct TlogLogger;
+
+fn level(level: log::Level) -> Level {
+    match level {
+        log::Level::Error => Level::Error,
+        log::Level::Warn => Level::Warn,
+        log::Level::Info => Level::Info,
+        log::Level::Debug | log::Level::Trace => Level::Debug,
+    }
+}
+
+impl log::Log for TlogLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        crate::__ENABLED && crate::level_enabled(level(metadata.level()))
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let site = match (record.file_static(), record.line()) {
+            (Some(file), Some(line)) => Some(Site { file, line, module: record.module_path_static().unwrap_or("") }),
+            _ => None,
+        };
+        let msg = format!("{}: {}", record.target(), record.args());
+        let _ = write_line(Some(level(record.level())), site, &msg);
+    }
+
+    fn flush(&self) {
+        let _ = crate::flush();
+    }
+}
+
+/// Install `TlogLogger` as the global `log` logger.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-log-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// tlog::init_log_backend().unwrap();
+/// log::warn!(target: "net", "connection reset");
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # assert!(content.ends_with("][WARN ] net: connection reset\n"), "{}", content);
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+pub fn init_log_backend() -> Result<(), log::SetLoggerError> {
+    static LOGGER: TlogLogger = TlogLogger;
+    log::set_logger(&LOGGER)?;
+    log::set_max_level(log::LevelFilter::Trace);
+    Ok(())
+}
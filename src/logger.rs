@@ -0,0 +1,495 @@
+//! What a line looks like, and `Logger` for writing them without any
+//! global or env state.
+
+use std::borrow::Cow;
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::template::{Segment, Template};
+use crate::{getpid, hostname, json, thread_name, DateTime, KvValue, Level, Precision, Site};
+
+/// The layout of a log line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// `[ts][pid] msg`.
+    #[default]
+    Text,
+    /// One JSON object per line: `{"ts":"...","pid":123,"msg":"..."}`.
+    Json,
+}
+
+impl Format {
+    /// `Json` for env `TMP_LOG_FORMAT=json`, else `Text`.
+    pub fn from_env() -> Self {
+        match std::env::var("TMP_LOG_FORMAT") {
+            Ok(x) if x.trim() == "json" => Format::Json,
+            _ => Format::Text,
+        }
+    }
+}
+
+/// How the lines after the first one of a multiline message are written,
+/// from env `TMP_LOG_MULTILINE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum Multiline {
+    /// As they are (the default).
+    #[default]
+    Raw,
+    /// Each with the full `[ts][pid]` header (`prefix`).
+    Prefix,
+    /// Indented to line up with the first line's message (`indent`).
+    Indent,
+}
+
+impl Multiline {
+    pub(crate) fn from_env() -> Self {
+        match std::env::var("TMP_LOG_MULTILINE") {
+            Ok(x) => match x.trim() {
+                "prefix" => Multiline::Prefix,
+                "indent" => Multiline::Indent,
+                _ => Multiline::Raw,
+            },
+            Err(_) => Multiline::Raw,
+        }
+    }
+}
+
+/// Where the timestamps come from, from env `TMP_LOG_CLOCK`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum Clock {
+    /// The local date and time (the default, `wall`).
+    #[default]
+    Wall,
+    /// Seconds since the process started logging, from the monotonic
+    /// clock (`mono`), unaffected by NTP or DST changes.
+    Mono,
+}
+
+impl Clock {
+    pub(crate) fn from_env() -> Self {
+        match std::env::var("TMP_LOG_CLOCK") {
+            Ok(x) if x.trim() == "mono" => Clock::Mono,
+            _ => Clock::Wall,
+        }
+    }
+}
+
+/// Everything that decides whether and how a line is written. The macros
+/// take a fresh `Config::from_env()` for every line; a `Logger` keeps
+/// its own.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Config {
+    /// Leveled lines above this one are dropped; `None` keeps them all.
+    pub(crate) max_level: Option<Level>,
+    pub(crate) format: Format,
+    pub(crate) multiline: Multiline,
+    pub(crate) precision: Precision,
+    pub(crate) clock: Clock,
+    pub(crate) tz: bool,
+    pub(crate) host: bool,
+    pub(crate) seq: bool,
+    pub(crate) tag: Option<String>,
+    pub(crate) version: Option<String>,
+    pub(crate) thread: bool,
+    pub(crate) loc: bool,
+    pub(crate) module: bool,
+    /// Messages longer than this many bytes are cut short.
+    pub(crate) max_msg: Option<usize>,
+    /// The layout of text lines from `TMP_LOG_TEMPLATE`, instead of the
+    /// usual header.
+    pub(crate) template: Option<Arc<Template>>,
+}
+
+impl Config {
+    pub(crate) fn from_env() -> Self {
+        let flag = crate::env_flag;
+        Config {
+            max_level: crate::max_level(),
+            format: Format::from_env(),
+            multiline: Multiline::from_env(),
+            precision: Precision::from_env(),
+            clock: Clock::from_env(),
+            tz: flag("TMP_LOG_TZ"),
+            host: flag("TMP_LOG_HOST"),
+            seq: flag("TMP_LOG_SEQ"),
+            tag: crate::tag(),
+            version: crate::version(),
+            thread: flag("TMP_LOG_THREAD"),
+            loc: flag("TMP_LOG_LOC"),
+            module: flag("TMP_LOG_MODULE"),
+            max_msg: std::env::var("TMP_LOG_MAX_MSG").ok().and_then(|x| x.trim().parse().ok()),
+            template: crate::template::from_env(),
+        }
+    }
+
+    pub(crate) fn enabled(&self, level: Option<Level>) -> bool {
+        match (level, self.max_level) {
+            (Some(level), Some(max)) => level <= max,
+            _ => true,
+        }
+    }
+
+    /// The full line(s) for `msg`, newline included. Sequence numbers are
+    /// taken from `seq` when they are on.
+    pub(crate) fn render(&self, level: Option<Level>, site: Option<Site>, msg: &str, seq: &AtomicU64) -> String {
+        let mut line = String::new();
+        self.render_into(&mut line, level, site, msg, &[], seq);
+        line
+    }
+
+    /// Like `render`, with `key=value` fields after the message, appending
+    /// to `out` so the caller can reuse its buffer.
+    pub(crate) fn render_into(
+        &self,
+        out: &mut String,
+        level: Option<Level>,
+        site: Option<Site>,
+        msg: &str,
+        fields: &[(&str, KvValue)],
+        seq: &AtomicU64,
+    ) {
+        let numbered = self.seq || self.template.as_ref().is_some_and(|t| t.uses(&Segment::Seq));
+        let seq = if numbered { Some(seq.fetch_add(1, Ordering::Relaxed)) } else { None };
+        let msg = msg.strip_suffix('\n').unwrap_or(msg);
+        let msg = truncate(msg, self.max_msg);
+        let msg = &*msg;
+        if self.format == Format::Json {
+            return self.json_line(out, level, site, seq, msg, fields);
+        }
+        let mut body = Cow::Borrowed(msg);
+        for (key, value) in fields {
+            let _ = write!(body.to_mut(), " {}={}", key, quote(&value.text));
+        }
+        if let Some(ref template) = self.template {
+            let ts = self.timestamp_string();
+            let parts: Vec<&str> = match self.multiline {
+                Multiline::Prefix => body.split('\n').collect(),
+                _ => vec![&body],
+            };
+            for part in parts {
+                self.templated(out, template, &ts, level, site, seq, part);
+            }
+            return;
+        }
+        if self.multiline == Multiline::Raw || !body.contains('\n') {
+            // the common case, written straight into `out`
+            self.header(out, level, site, seq);
+            out.push_str(&body);
+            out.push('\n');
+        } else {
+            let mut header = String::new();
+            self.header(&mut header, level, site, seq);
+            format_line(out, &header, &body, self.multiline);
+        }
+    }
+
+    /// Append a line laid out by `template` to `out`. The timestamp is
+    /// passed in so every line of a message shares it.
+    #[allow(clippy::too_many_arguments)]
+    fn templated(
+        &self,
+        out: &mut String,
+        template: &Template,
+        ts: &str,
+        level: Option<Level>,
+        site: Option<Site>,
+        seq: Option<u64>,
+        msg: &str,
+    ) {
+        for segment in &template.segments {
+            let _ = match *segment {
+                Segment::Literal(ref s) => write!(out, "{}", s),
+                Segment::Ts => write!(out, "{}", ts),
+                Segment::Pid => write!(out, "{}", getpid()),
+                Segment::Host => write!(out, "{}", hostname()),
+                Segment::Seq => write!(out, "{:06}", seq.unwrap_or(0)),
+                Segment::Tag => write!(out, "{}", self.tag.as_deref().unwrap_or("")),
+                Segment::Version => write!(out, "{}", self.version.as_deref().unwrap_or("")),
+                Segment::Thread => write!(out, "{}", thread_name()),
+                Segment::Level => write!(out, "{}", level.map_or("", |l| l.as_str())),
+                Segment::File => write!(out, "{}", site.map_or("", |s| s.file)),
+                Segment::Line => match site {
+                    Some(site) => write!(out, "{}", site.line),
+                    None => Ok(()),
+                },
+                Segment::Module => write!(out, "{}", site.map_or("", |s| s.module)),
+                Segment::Msg => write!(out, "{}", msg),
+            };
+        }
+        out.push('\n');
+    }
+
+    fn timestamp_string(&self) -> String {
+        let mut ts = String::new();
+        self.timestamp(&mut ts);
+        ts
+    }
+
+    fn timestamp(&self, out: &mut String) {
+        if self.clock == Clock::Mono {
+            let secs = crate::start().elapsed().as_secs_f64();
+            let _ = match self.precision {
+                Precision::Millis => write!(out, "{:.3}", secs),
+                Precision::Micros => write!(out, "{:.6}", secs),
+                Precision::Nanos => write!(out, "{:.9}", secs),
+            };
+            return;
+        }
+        let now = DateTime::now();
+        now.write_with_precision(out, self.precision);
+        if self.tz {
+            out.push_str(&now.offset_suffix());
+        }
+    }
+
+    /// Append everything in front of the message, including the
+    /// separating space, to `out`.
+    fn header(&self, out: &mut String, level: Option<Level>, site: Option<Site>, seq: Option<u64>) {
+        out.push('[');
+        self.timestamp(out);
+        let _ = write!(out, "][{}]", getpid());
+        if self.host {
+            let _ = write!(out, "[{}]", hostname());
+        }
+        if let Some(seq) = seq {
+            let _ = write!(out, "[#{:06}]", seq);
+        }
+        if let Some(ref tag) = self.tag {
+            let _ = write!(out, "[{}]", tag);
+        }
+        if let Some(ref version) = self.version {
+            let _ = write!(out, "[{}]", version);
+        }
+        if self.thread {
+            let _ = write!(out, "[{}]", thread_name());
+        }
+        if let Some(site) = site {
+            if self.module {
+                let _ = write!(out, "[{}]", site.module);
+            }
+        }
+        if let Some(level) = level {
+            let _ = write!(out, "[{:<5}]", level);
+        }
+        out.push(' ');
+        if let Some(site) = site {
+            if self.loc {
+                let _ = write!(out, "{}:{}: ", site.file, site.line);
+            }
+        }
+    }
+
+    /// Append a whole JSON line, `{"ts":"...","pid":123,"msg":"..."}` plus
+    /// the optional fields that are on, to `out`.
+    fn json_line(
+        &self,
+        out: &mut String,
+        level: Option<Level>,
+        site: Option<Site>,
+        seq: Option<u64>,
+        msg: &str,
+        fields: &[(&str, KvValue)],
+    ) {
+        let ts = self.timestamp_string();
+        out.push('{');
+        json::push_field(out, "ts", &ts);
+        json::push_raw_field(out, "pid", &getpid().to_string());
+        if self.host {
+            json::push_field(out, "host", hostname());
+        }
+        if let Some(seq) = seq {
+            json::push_raw_field(out, "seq", &seq.to_string());
+        }
+        if let Some(ref tag) = self.tag {
+            json::push_field(out, "tag", tag);
+        }
+        if let Some(ref version) = self.version {
+            json::push_field(out, "version", version);
+        }
+        if self.thread {
+            json::push_field(out, "thread", &thread_name());
+        }
+        if let Some(level) = level {
+            json::push_field(out, "level", level.as_str());
+        }
+        if let Some(site) = site {
+            if self.loc {
+                json::push_field(out, "file", site.file);
+                json::push_raw_field(out, "line", &site.line.to_string());
+            }
+            if self.module {
+                json::push_field(out, "module", site.module);
+            }
+        }
+        json::push_field(out, "msg", msg);
+        for (key, value) in fields {
+            // a field can't stand in for one of the line's own keys
+            let key = match *key {
+                "ts" | "pid" | "host" | "seq" | "tag" | "version" | "thread" | "level" | "file" | "line"
+                | "module" | "msg" => Cow::Owned(format!("field.{}", key)),
+                key => Cow::Borrowed(key),
+            };
+            if value.raw {
+                json::push_raw_field(out, &key, &value.text);
+            } else {
+                json::push_field(out, &key, &value.text);
+            }
+        }
+        out.push_str("}\n");
+    }
+}
+
+/// `msg` cut down to at most `max` bytes, on a char boundary, with a
+/// `…[truncated N bytes]` marker saying how much is missing.
+fn truncate(msg: &str, max: Option<usize>) -> Cow<'_, str> {
+    let max = match max {
+        Some(max) if msg.len() > max => max,
+        _ => return Cow::Borrowed(msg),
+    };
+    let mut end = max;
+    while !msg.is_char_boundary(end) {
+        end -= 1;
+    }
+    Cow::Owned(format!("{}…[truncated {} bytes]", &msg[..end], msg.len() - end))
+}
+
+/// A field value as it is written in a text line: quoted (and escaped)
+/// when it is empty or has spaces or quotes in it.
+fn quote(value: &str) -> String {
+    if value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '"') {
+        format!("{:?}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Append `header` and `body` to `out`, one line per line of `body`.
+fn format_line(out: &mut String, header: &str, body: &str, mode: Multiline) {
+    match mode {
+        Multiline::Raw => {
+            out.push_str(header);
+            out.push_str(body);
+            out.push('\n');
+        }
+        Multiline::Prefix => {
+            for part in body.split('\n') {
+                out.push_str(header);
+                out.push_str(part);
+                out.push('\n');
+            }
+        }
+        Multiline::Indent => {
+            let indent = " ".repeat(header.chars().count());
+            for (i, part) in body.split('\n').enumerate() {
+                out.push_str(if i == 0 { header } else { &indent });
+                out.push_str(part);
+                out.push('\n');
+            }
+        }
+    }
+}
+
+/// A logger of its own, for libraries and for writing to several files
+/// at once. Unlike the macros it ignores the `TMP_LOG_*` env and the
+/// process wide settings such as `set_tag`; everything is set when it is
+/// built.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// use tlog::{Format, Level, Logger};
+///
+/// # let dir = std::env::temp_dir().join(format!("tlog-logger-{}", std::process::id()));
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let web = Logger::new(dir.join("web.log")).tag("web");
+/// let db = Logger::new(dir.join("db.log")).level(Level::Warn).format(Format::Json);
+///
+/// web.log("GET /").unwrap();
+/// db.log_at(Level::Info, "dropped").unwrap();
+/// db.log_at(Level::Error, "disk full").unwrap();
+///
+/// let web_log = std::fs::read_to_string(dir.join("web.log")).unwrap();
+/// let db_log = std::fs::read_to_string(dir.join("db.log")).unwrap();
+/// assert!(web_log.ends_with("][web] GET /\n"));
+/// assert_eq!(db_log.lines().count(), 1);
+/// assert!(db_log.contains(r#""level":"ERROR","msg":"disk full""#));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Logger {
+    path: PathBuf,
+    config: Config,
+    seq: AtomicU64,
+    file: Mutex<Option<File>>,
+}
+
+impl Logger {
+    /// A logger appending plain text lines to `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Logger {
+            path: path.into(),
+            config: Config::default(),
+            seq: AtomicU64::new(1),
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Add a `[tag]` field to every line. An empty tag removes it.
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        let tag = tag.into();
+        self.config.tag = if tag.is_empty() { None } else { Some(tag) };
+        self
+    }
+
+    /// Add a `[version]` field to every line. An empty version removes it.
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        let version = version.into();
+        self.config.version = if version.is_empty() { None } else { Some(version) };
+        self
+    }
+
+    /// Drop the lines of `log_at` below `level`.
+    pub fn level(mut self, level: Level) -> Self {
+        self.config.max_level = Some(level);
+        self
+    }
+
+    pub fn format(mut self, format: Format) -> Self {
+        self.config.format = format;
+        self
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Write `msg` with the usual header.
+    pub fn log(&self, msg: &str) -> io::Result<()> {
+        self.write(None, msg)
+    }
+
+    /// Write `msg` with a level field, unless it is filtered out by
+    /// `level`.
+    pub fn log_at(&self, level: Level, msg: &str) -> io::Result<()> {
+        self.write(Some(level), msg)
+    }
+
+    fn write(&self, level: Option<Level>, msg: &str) -> io::Result<()> {
+        if !crate::__ENABLED || !self.config.enabled(level) {
+            return Ok(());
+        }
+        let line = self.config.render(level, None, msg, &self.seq);
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if file.is_none() {
+            *file = Some(OpenOptions::new().append(true).create(true).open(&self.path)?);
+        }
+        match *file {
+            Some(ref mut f) => f.write_all(line.as_bytes()),
+            None => Ok(()),
+        }
+    }
+}
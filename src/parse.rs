@@ -0,0 +1,40 @@
+//! Reading log lines back.
+
+use crate::datetime::{self, DateTime};
+
+/// One line of the log file, as read back by `parse_line`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub ts: DateTime,
+    pub pid: i32,
+    /// Everything after the `[pid]` field. Optional fields such as
+    /// `[WARN ]` or a tag are kept at the start of it.
+    pub msg: String,
+}
+
+/// Parse a `[ts][pid] msg` line written by `tlog!`, or `None` if it
+/// doesn't look like one. A trailing newline is ignored.
+///
+/// ```
+/// let entry = tlog::parse_line("[2022-09-05 11:10:31.763][15235] a [b] c\n").unwrap();
+/// assert_eq!(entry.ts.to_string(), "2022-09-05 11:10:31.763");
+/// assert_eq!(entry.pid, 15235);
+/// assert_eq!(entry.msg, "a [b] c");
+///
+/// assert!(tlog::parse_line("not a log line").is_none());
+/// assert!(tlog::parse_line("[2022-09-05 11:10:31.763][pid] x").is_none());
+/// ```
+pub fn parse_line(line: &str) -> Option<LogEntry> {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    let rest = line.strip_prefix('[')?;
+    let (ts, rest) = rest.split_once("][")?;
+    let (pid, rest) = rest.split_once(']')?;
+    if pid.is_empty() || !pid.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(LogEntry {
+        ts: datetime::parse(ts)?,
+        pid: pid.parse().ok()?,
+        msg: rest.strip_prefix(' ').unwrap_or(rest).to_string(),
+    })
+}
@@ -0,0 +1,142 @@
+//! Rotation of the log file, by size (`t.log` -> `t.log.1` -> `t.log.2`
+//! ...) or by day (`t.log` -> `t.log.2022-09-05`). With the `gzip`
+//! feature and `TMP_LOG_GZIP=1` the rotated files are compressed to
+//! `t.log.1.gz` and `t.log.2022-09-05.gz`.
+
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::time::UNIX_EPOCH;
+
+use crate::DateTime;
+
+/// Number of rotated files kept when `TMP_LOG_MAX_FILES` is unset.
+const DEFAULT_MAX_FILES: usize = 3;
+
+fn env_number(name: &str) -> Option<u64> {
+    std::env::var(name).ok().and_then(|x| x.trim().parse().ok())
+}
+
+/// The rotation threshold from env `TMP_LOG_MAX_BYTES`, if any.
+pub(crate) fn max_bytes() -> Option<u64> {
+    env_number("TMP_LOG_MAX_BYTES").filter(|&n| n > 0)
+}
+
+/// How many rotated files to keep, from env `TMP_LOG_MAX_FILES`.
+pub(crate) fn max_files() -> usize {
+    match env_number("TMP_LOG_MAX_FILES") {
+        Some(n) => n as usize,
+        None => DEFAULT_MAX_FILES,
+    }
+}
+
+/// Whether a file of `len` bytes must be rotated before appending `extra`
+/// more. An empty file is never rotated, so a single huge line still
+/// gets written.
+pub(crate) fn needs_rotation(len: u64, extra: usize, max_bytes: u64) -> bool {
+    len > 0 && len + extra as u64 > max_bytes
+}
+
+/// Shift `path.1 .. path.{keep-1}` up by one, move `path` to `path.1` and
+/// drop whatever falls off the end. With `keep == 0` the file is simply
+/// removed. Compressed `path.N.gz` files are shifted and dropped the same
+/// way as plain ones.
+///
+/// Each step is a `rename`, so a writer holding the old handle keeps
+/// appending whole lines to what becomes `path.1`.
+pub(crate) fn rotate(path: &str, keep: usize) -> io::Result<()> {
+    if keep == 0 {
+        return remove_if_exists(path);
+    }
+    remove_if_exists(&format!("{}.{}", path, keep))?;
+    remove_if_exists(&format!("{}.{}.gz", path, keep))?;
+    for i in (1..keep).rev() {
+        rename_if_exists(&format!("{}.{}", path, i), &format!("{}.{}", path, i + 1))?;
+        rename_if_exists(&format!("{}.{}.gz", path, i), &format!("{}.{}.gz", path, i + 1))?;
+    }
+    let rotated = format!("{}.1", path);
+    rename_if_exists(path, &rotated)?;
+    if gzip() {
+        compress(&rotated)?;
+    }
+    Ok(())
+}
+
+/// Whether env `TMP_LOG_GZIP` asks for compressed rotated files, and the
+/// `gzip` feature makes it possible.
+fn gzip() -> bool {
+    cfg!(feature = "gzip") && crate::env_flag("TMP_LOG_GZIP")
+}
+
+/// Compress `path` into `path.gz` and remove it. An existing `path.gz`
+/// gets the new data as one more gzip member, which decompresses as if
+/// the two had been compressed together.
+#[cfg(feature = "gzip")]
+fn compress(path: &str) -> io::Result<()> {
+    let mut from = match fs::File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let to = OpenOptions::new().append(true).create(true).open(format!("{}.gz", path))?;
+    let mut encoder = flate2::write::GzEncoder::new(to, flate2::Compression::default());
+    io::copy(&mut from, &mut encoder)?;
+    encoder.finish()?;
+    remove_if_exists(path)
+}
+
+#[cfg(not(feature = "gzip"))]
+fn compress(_path: &str) -> io::Result<()> {
+    Ok(())
+}
+
+/// Whether env `TMP_LOG_DAILY` asks for a file per day.
+pub(crate) fn daily() -> bool {
+    crate::env_flag("TMP_LOG_DAILY")
+}
+
+/// The local day `path` was last modified on.
+pub(crate) fn file_day(path: &str) -> Option<time::Date> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    let secs = modified.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some(DateTime::from_unix_timestamp(secs as i64).ok()?.date())
+}
+
+/// Move `path` to `path.YYYY-MM-DD` for `day`. Should that file exist
+/// already, for instance after changing the clock, `path` is appended
+/// to it rather than replacing it.
+pub(crate) fn rotate_daily(path: &str, day: time::Date) -> io::Result<()> {
+    let dated = format!("{}.{}", path, day);
+    move_or_append(path, &dated)?;
+    if gzip() {
+        compress(&dated)?;
+    }
+    Ok(())
+}
+
+fn move_or_append(path: &str, dated: &str) -> io::Result<()> {
+    if fs::metadata(dated).is_err() {
+        return rename_if_exists(path, dated);
+    }
+    let mut from = match fs::File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let mut to = OpenOptions::new().append(true).open(dated)?;
+    io::copy(&mut from, &mut to)?;
+    remove_if_exists(path)
+}
+
+fn remove_if_exists(path: &str) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn rename_if_exists(from: &str, to: &str) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,141 @@
+//! Following a log file as it grows, like `tail -f`.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long `Tail` sleeps before looking at the file again.
+const POLL: Duration = Duration::from_millis(50);
+
+/// The lines of a log file, see `tail`.
+#[derive(Debug)]
+pub struct Tail {
+    path: PathBuf,
+    reader: Option<BufReader<File>>,
+    /// Bytes read from the current file so far.
+    pos: u64,
+    /// The start of a line whose newline has not been written yet.
+    partial: String,
+}
+
+/// Iterate over the lines of the log file at `path`, without their
+/// newlines: first the ones already there, then, blocking, each new one
+/// as it is appended. A line is only yielded once its newline has been
+/// written. If the file is missing, it is waited for.
+///
+/// When the file is truncated or replaced (by rotation, or by `rm`),
+/// reading starts over at the beginning of the new one.
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-tail-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use std::sync::mpsc;
+/// use std::time::Duration;
+///
+/// tlog::tlog!("before");
+/// let lines = tlog::tail(&path);
+/// let (tx, rx) = mpsc::channel();
+/// std::thread::spawn(move || {
+///     for line in lines {
+///         if tx.send(line.unwrap()).is_err() {
+///             break;
+///         }
+///     }
+/// });
+///
+/// let next = || rx.recv_timeout(Duration::from_secs(10)).expect("no line in time");
+/// assert!(next().ends_with("] before"));
+///
+/// // the reader is now waiting for more
+/// for i in 0..3 {
+///     tlog::tlog!("after {}", i);
+/// }
+/// let got: Vec<String> = (0..3).map(|_| next()).collect();
+/// assert!(got[0].ends_with("] after 0"));
+/// assert!(got[2].ends_with("] after 2"));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+pub fn tail(path: impl AsRef<Path>) -> Tail {
+    Tail {
+        path: path.as_ref().to_path_buf(),
+        reader: None,
+        pos: 0,
+        partial: String::new(),
+    }
+}
+
+impl Tail {
+    /// Whether the file at the path is no longer the one being read: it
+    /// is shorter than what was read from it, or another file altogether.
+    fn replaced(&self) -> bool {
+        let reader = match self.reader {
+            Some(ref reader) => reader,
+            None => return false,
+        };
+        let (current, opened) = match (std::fs::metadata(&self.path), reader.get_ref().metadata()) {
+            (Ok(current), Ok(opened)) => (current, opened),
+            // removed: wait for the new one
+            (Err(_), _) => return true,
+            (_, Err(_)) => return false,
+        };
+        if current.len() < self.pos {
+            return true;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            if (current.dev(), current.ino()) != (opened.dev(), opened.ino()) {
+                return true;
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = opened;
+        false
+    }
+}
+
+impl Iterator for Tail {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let reader = match self.reader {
+                Some(ref mut reader) => reader,
+                None => match File::open(&self.path) {
+                    Ok(file) => {
+                        self.pos = 0;
+                        self.reader.insert(BufReader::new(file))
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                        std::thread::sleep(POLL);
+                        continue;
+                    }
+                    Err(e) => return Some(Err(e)),
+                },
+            };
+            match reader.read_line(&mut self.partial) {
+                Ok(0) => {}
+                Ok(n) => {
+                    self.pos += n as u64;
+                    if self.partial.ends_with('\n') {
+                        let mut line = std::mem::take(&mut self.partial);
+                        line.pop();
+                        return Some(Ok(line));
+                    }
+                    // the rest of the line is still to come
+                    continue;
+                }
+                Err(e) => return Some(Err(e)),
+            }
+            if self.replaced() {
+                self.reader = None;
+                self.partial.clear();
+                continue;
+            }
+            std::thread::sleep(POLL);
+        }
+    }
+}
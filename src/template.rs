@@ -0,0 +1,106 @@
+//! `TMP_LOG_TEMPLATE`: a custom layout for text lines.
+
+use std::sync::{Arc, Mutex};
+
+/// A piece of a compiled template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Segment {
+    Literal(String),
+    Ts,
+    Pid,
+    Host,
+    Seq,
+    Tag,
+    Version,
+    Thread,
+    Level,
+    File,
+    Line,
+    Module,
+    Msg,
+}
+
+impl Segment {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "ts" => Segment::Ts,
+            "pid" => Segment::Pid,
+            "host" => Segment::Host,
+            "seq" => Segment::Seq,
+            "tag" => Segment::Tag,
+            "version" => Segment::Version,
+            "thread" => Segment::Thread,
+            "level" => Segment::Level,
+            "file" => Segment::File,
+            "line" => Segment::Line,
+            "module" => Segment::Module,
+            "msg" => Segment::Msg,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Template {
+    pub(crate) segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Split `s` into literal text and `{name}` placeholders. Unknown
+    /// placeholders are kept as literal text. Without a `{msg}`, the
+    /// message is added at the end after a space.
+    pub(crate) fn compile(s: &str) -> Self {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            literal.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let segment = rest.find('}').and_then(|end| Some((Segment::from_name(&rest[1..end])?, end)));
+            match segment {
+                Some((segment, end)) => {
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(segment);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    literal.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        literal.push_str(rest);
+        if !segments.contains(&Segment::Msg) {
+            literal.push(' ');
+            segments.push(Segment::Literal(std::mem::take(&mut literal)));
+            segments.push(Segment::Msg);
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Template { segments }
+    }
+
+    pub(crate) fn uses(&self, segment: &Segment) -> bool {
+        self.segments.contains(segment)
+    }
+}
+
+/// The template from env `TMP_LOG_TEMPLATE`, compiled once and kept
+/// until the env changes.
+pub(crate) fn from_env() -> Option<Arc<Template>> {
+    static CACHE: Mutex<Option<(String, Arc<Template>)>> = Mutex::new(None);
+
+    let source = std::env::var("TMP_LOG_TEMPLATE").ok().filter(|x| !x.is_empty())?;
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    match *cache {
+        Some((ref s, ref template)) if *s == source => Some(template.clone()),
+        _ => {
+            let template = Arc::new(Template::compile(&source));
+            *cache = Some((source, template.clone()));
+            Some(template)
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! A `tracing-subscriber` layer, with the `tracing` feature.
+
+use std::fmt::{self, Write as _};
+
+use tracing_core::field::{Field, Visit};
+use tracing_core::span::Id;
+use tracing_core::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::{write_line, Level, Site};
+
+/// Writes `tracing` events as `tlog` lines, next to whatever other
+/// layers the subscriber has:
+///
+/// ```text
+/// [2022-09-05 11:10:31.763][15235][INFO ] my_crate::auth: login user=bob
+/// ```
+///
+/// ```
+/// # #[cfg(feature = "enabled")] {
+/// # let path = std::env::temp_dir().join(format!("tlog-tracing-{}.log", std::process::id()));
+/// # std::env::set_var("TMP_LOG_FILE", &path);
+/// use tracing_subscriber::layer::SubscriberExt;
+///
+/// let subscriber = tracing_subscriber::registry().with(tlog::TlogLayer::new().with_spans(true));
+/// tracing::subscriber::with_default(subscriber, || {
+///     let _span = tracing::info_span!("request").entered();
+///     tracing::info!(target: "auth", user = "bob", attempt = 2, "login");
+/// });
+/// # let content = std::fs::read_to_string(&path).unwrap();
+/// # let lines: Vec<_> = content.lines().collect();
+/// # assert_eq!(lines.len(), 3, "{}", content);
+/// # assert!(lines[0].ends_with("-> request"));
+/// # assert!(lines[1].ends_with("][INFO ] auth: login user=bob attempt=2"));
+/// # assert!(lines[2].ends_with("<- request"));
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct TlogLayer {
+    spans: bool,
+}
+
+impl TlogLayer {
+    pub fn new() -> Self {
+        TlogLayer::default()
+    }
+
+    /// Also log `-> name` and `<- name` lines when entering and leaving
+    /// spans.
+    pub fn with_spans(mut self, spans: bool) -> Self {
+        self.spans = spans;
+        self
+    }
+}
+
+fn level(level: &tracing_core::Level) -> Level {
+    match *level {
+        tracing_core::Level::ERROR => Level::Error,
+        tracing_core::Level::WARN => Level::Warn,
+        tracing_core::Level::INFO => Level::Info,
+        _ => Level::Debug,
+    }
+}
+
+/// Collects the `message` field and renders the others as `k=v`.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    rest: String,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_debug(field, &format_args!("{}", value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.rest, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+impl<S> Layer<S> for TlogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let meta = event.metadata();
+        let level = level(meta.level());
+        if !crate::__ENABLED || !crate::level_enabled(level) {
+            return;
+        }
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let site = match (meta.file(), meta.line()) {
+            (Some(file), Some(line)) => Some(Site { file, line, module: meta.module_path().unwrap_or("") }),
+            _ => None,
+        };
+        let msg = format!("{}: {}{}", meta.target(), fields.message, fields.rest);
+        let _ = write_line(Some(level), site, &msg);
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        self.log_span(id, ctx, "->");
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        self.log_span(id, ctx, "<-");
+    }
+}
+
+impl TlogLayer {
+    fn log_span<S>(&self, id: &Id, ctx: Context<'_, S>, arrow: &str)
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        if !self.spans {
+            return;
+        }
+        if let Some(span) = ctx.span(id) {
+            let meta = span.metadata();
+            let level = level(meta.level());
+            if crate::__ENABLED && crate::level_enabled(level) {
+                let msg = format!("{}: {} {}", meta.target(), arrow, meta.name());
+                let _ = write_line(Some(level), None, &msg);
+            }
+        }
+    }
+}